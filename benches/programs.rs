@@ -0,0 +1,121 @@
+//! runs the programs in `benches/programs` through the optimizer and the
+//! interpreter and prints how long each took. there are no dependencies,
+//! so this is a plain binary instead of a criterion harness:
+//!
+//!     cargo bench
+//!     cargo bench -- rot13
+//!
+//! every program runs until it has taken at least a second in total, and
+//! the best run is what gets reported. the programs that write a lot also
+//! run writing to a file, buffered the way stdout is and the way `-o` is.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::{Duration, Instant};
+
+use brainfuckers::{execute, flatten, optimize_opcodes, parse, tokenize, FlatInstr, Options, Stats};
+
+/// a program to run, and how to make the input it reads
+struct Benchmark {
+    name: &'static str,
+    source: &'static str,
+    input: fn() -> Vec<u8>,
+    /// whether to also time writing the output to a file
+    writes_file: bool,
+}
+
+const PROGRAMS: &[Benchmark] = &[
+    Benchmark { name: "hello", source: include_str!("programs/hello.bf"), input: Vec::new, writes_file: false },
+    Benchmark { name: "loops", source: include_str!("programs/loops.bf"), input: Vec::new, writes_file: false },
+    Benchmark { name: "rot13", source: include_str!("programs/rot13.bf"), input: rot13_input, writes_file: false },
+    Benchmark { name: "cat", source: include_str!("programs/cat.bf"), input: cat_input, writes_file: true },
+    Benchmark { name: "bytes", source: include_str!("programs/bytes.bf"), input: Vec::new, writes_file: true },
+];
+
+/// how to write files: the way stdout is written, through `BufWriter`'s
+/// default buffer that's flushed before every read, and the way `-o` does
+const FILE_OUTPUTS: &[(&str, usize, bool)] = &[("stdout", 8 * 1024, false), ("-o", 1 << 20, true)];
+
+const MIN_TOTAL: Duration = Duration::from_secs(1);
+
+/// 64K of text to shift around
+fn rot13_input() -> Vec<u8> {
+    b"The quick brown fox jumps over the lazy dog. ".iter().cycle().take(1 << 16).copied().collect()
+}
+
+/// a megabyte to copy, to see how fast `,` and `.` go
+fn cat_input() -> Vec<u8> {
+    (0..1 << 20).map(|i| b'a' + (i % 26) as u8).collect()
+}
+
+/// runs `program` once and returns how long it took
+fn time_run(program: &[FlatInstr], input: &[u8], stats: Option<&mut Stats>) -> Duration {
+    let mut tape = vec![0u8; 30000];
+    let mut output = Vec::new();
+    let start = Instant::now();
+    execute(program, &mut tape, &mut 0, &mut &input[..], &mut output, &Options::default(), stats).unwrap();
+    start.elapsed()
+}
+
+/// runs `program` once writing to a file through a buffer of `buffer`
+/// bytes, and returns how long it took with flushing and closing the file
+fn time_file_run(program: &[FlatInstr], input: &[u8], buffer: usize, buffer_across_reads: bool) -> Duration {
+    let path = std::env::temp_dir().join(format!("brainfuckers-bench-{}.out", std::process::id()));
+    let mut tape = vec![0u8; 30000];
+    let start = Instant::now();
+    let mut output = BufWriter::with_capacity(buffer, File::create(&path).unwrap());
+    execute(program, &mut tape, &mut 0, &mut &input[..], &mut output, &Options { buffer_across_reads, ..Options::default() }, None).unwrap();
+    output.flush().unwrap();
+    drop(output);
+    let elapsed = start.elapsed();
+    std::fs::remove_file(&path).unwrap();
+    elapsed
+}
+
+/// runs `run` until it has taken at least `MIN_TOTAL`, and returns the best
+/// run and how many runs there were
+fn best_of(mut run: impl FnMut() -> Duration) -> (Duration, u32) {
+    let mut runs = 0;
+    let mut total = Duration::ZERO;
+    let mut best = Duration::MAX;
+    while total < MIN_TOTAL {
+        let elapsed = run();
+        runs += 1;
+        total += elapsed;
+        best = best.min(elapsed);
+    }
+    (best, runs)
+}
+
+fn main() {
+    // cargo passes `--bench` along, anything else picks programs by name
+    let filters: Vec<String> = std::env::args().skip(1).filter(|arg| !arg.starts_with('-')).collect();
+
+    for Benchmark { name, source, input, writes_file } in PROGRAMS {
+        if !filters.is_empty() && !filters.iter().any(|filter| name.contains(filter.as_str())) {
+            continue;
+        }
+        let input = input();
+
+        let start = Instant::now();
+        let mut opcodes = tokenize(source);
+        optimize_opcodes(&mut opcodes);
+        let program = flatten(&parse(opcodes).unwrap());
+        let optimize_time = start.elapsed();
+
+        // one run with stats to know how much work a run is
+        let mut stats = Stats::default();
+        time_run(&program, &input, Some(&mut stats));
+
+        let (best, runs) = best_of(|| time_run(&program, &input, None));
+        println!("{:<8} optimize {:>10.2?}  run {:>10.2?}  ({} runs, {:.0} instructions/sec)",
+            name, optimize_time, best, runs, stats.total() as f64 / best.as_secs_f64());
+
+        if *writes_file {
+            for (label, buffer, buffer_across_reads) in FILE_OUTPUTS {
+                let (best, runs) = best_of(|| time_file_run(&program, &input, *buffer, *buffer_across_reads));
+                println!("{:<8} file like {:<8}        run {:>10.2?}  ({} runs)", name, label, best, runs);
+            }
+        }
+    }
+}
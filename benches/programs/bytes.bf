@@ -0,0 +1,5 @@
+writes every byte from 0 to 255 over and over until it has written two megabytes
+like a program drawing a big picture would so this mostly measures output
+
+++++++++++++++++++++++++++++++++
+[>-[>-[>>.+<<-]<-]<-]
@@ -0,0 +1,6 @@
+copies everything on input to output until it runs out
+
+-,+[                            read a character and add one so end of input (unchanged at minus one) is zero
+    -.                          take the one away again and write the character
+    [-]-,+                      read the next one the same way
+]
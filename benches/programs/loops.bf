@@ -0,0 +1,10 @@
+deeply nested counting loops that print ZYXWVUTSRQPONMLKJIHGFEDCBA
+the inner loops do nothing but count down so this mostly measures loop overhead
+
+>++[<+++++++++++++>-]<
+[
+  [>+>+<<-]>[<+>-]++++++++[>++++++++<-]>.[-]<<
+  >++++++++++[>++++++++++[>++++++++++[>++++++++++[>++++++++++[>++++++++++[-]<-]<-]<-]<-]<-]
+  <-
+]
+++++++++++.
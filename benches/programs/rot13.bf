@@ -0,0 +1,29 @@
+rot13 of everything on input until it runs out
+(the commented version from wikipedia)
+
+-,+[                            read first character and start outer character reading loop
+    -[                          skip forward if character is 0
+        >>++++[>++++++++<-]     set up divisor (32) for division loop
+        <+<-[                   set up dividend (x minus 1) and enter division loop
+            >+>+>-[>>>]         increase copy and remainder / reduce divisor / normal case / skip forward
+            <[[>+<-]>>+>]       special case / move remainder back to divisor and increase quotient
+            <<<<<-              decrement dividend
+        ]                       end division loop
+    ]>>>[-]+                    end skip loop / zero former divisor and reuse space for a flag
+    >--[-[<->+++[-]]]<[         zero that flag unless quotient was 2 or 3 / zero quotient / check flag
+        ++++++++++++<[          if flag then set up divisor (13) for second division loop
+            >-[>+>>]            reduce divisor / normal case / increase remainder
+            >[+[<+>-]>+>>]      special case / increase remainder / move it back to divisor / increase quotient
+            <<<<<-              decrease dividend
+        ]                       end division loop
+        >>[<+>-]                add remainder back to divisor to get a useful 13
+        >[                      skip forward if quotient was 0
+            -[                  decrement quotient and skip forward if quotient was 1
+                -<<[-]>>        zero quotient and divisor if quotient was 2
+            ]<<[<<->>-]>>       zero divisor and subtract 13 from copy if quotient was 1
+        ]<<[<<+>>-]             zero divisor and add 13 to copy if quotient was 0
+    ]                           end outer skip loop (jump to here if ((character minus 1)/32) was not 2 or 3)
+    <[-]                        clear remainder from first division if second division was skipped
+    <.[-]                       output rot13ed character from copy and clear it
+    <-,+                        read next character
+]                               end character reading loop
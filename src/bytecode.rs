@@ -0,0 +1,195 @@
+//! a small binary format for flattened programs, so the optimizer
+//! doesn't have to run again every time a program is started.
+//!
+//! the file starts with `MAGIC` and a version byte, followed by the number
+//! of instructions as a little endian u32. every instruction is a tag byte
+//! followed by its operands, all little endian.
+
+use crate::FlatInstr;
+
+/// what every compiled file starts with
+pub const MAGIC: &[u8; 3] = b"BFC";
+/// bumped whenever the encoding changes
+pub const VERSION: u8 = 1;
+
+/// why a compiled file couldn't be read back
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodeError {
+    /// the file doesn't start with `MAGIC`
+    NotBytecode,
+    /// the file was written by an incompatible version
+    UnsupportedVersion(u8),
+    /// the file ends in the middle of an instruction
+    Truncated,
+    /// an instruction tag that doesn't exist, with its byte offset
+    UnknownTag(u8, usize),
+    /// a jump that leaves the program, or doesn't pair up with the other
+    /// end of its loop like `flatten` would have written it, with its byte offset
+    BadJump(usize),
+    /// a `ClearRange` of no cells, with its byte offset
+    EmptyClearRange(usize),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::NotBytecode => write!(f, "not a compiled brainfuck program"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported bytecode version {} (expected {})", v, VERSION),
+            DecodeError::Truncated => write!(f, "bytecode ends unexpectedly"),
+            DecodeError::UnknownTag(tag, at) => write!(f, "unknown instruction tag {} at byte {}", tag, at),
+            DecodeError::BadJump(at) => write!(f, "jump at byte {} doesn't match up with its loop", at),
+            DecodeError::EmptyClearRange(at) => write!(f, "clear range of no cells at byte {}", at),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// checks whether `bytes` look like a compiled program
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn encode(program: &[FlatInstr]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(8 + program.len() * 5);
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.extend_from_slice(&(program.len() as u32).to_le_bytes());
+
+    for instr in program {
+        match instr {
+            FlatInstr::Move(offset) => { out.push(0); out.extend_from_slice(&offset.to_le_bytes()); }
+            FlatInstr::Add(count) => { out.push(1); out.extend_from_slice(&count.to_le_bytes()); }
+            FlatInstr::Sub(count) => { out.push(2); out.extend_from_slice(&count.to_le_bytes()); }
+            FlatInstr::Write => out.push(3),
+            FlatInstr::Read => out.push(4),
+            FlatInstr::ResetCell => out.push(5),
+            FlatInstr::ScanCells(right) => { out.push(6); out.push(*right as u8); }
+            FlatInstr::MultiplyMove(targets) => {
+                out.push(7);
+                out.extend_from_slice(&(targets.len() as u32).to_le_bytes());
+                for (offset, multiplier) in targets {
+                    out.extend_from_slice(&offset.to_le_bytes());
+                    out.extend_from_slice(&multiplier.to_le_bytes());
+                }
+            }
+            FlatInstr::AddAt(offset, count) => {
+                out.push(8);
+                out.extend_from_slice(&offset.to_le_bytes());
+                out.extend_from_slice(&count.to_le_bytes());
+            }
+            FlatInstr::TapeState => out.push(9),
+            FlatInstr::TapeWindow => out.push(12),
+            FlatInstr::MoveCell(offset) => { out.push(13); out.extend_from_slice(&offset.to_le_bytes()); }
+            FlatInstr::SetCell(value) => { out.push(14); out.extend_from_slice(&value.to_le_bytes()); }
+            FlatInstr::InfiniteLoop => out.push(15),
+            FlatInstr::ClearRange(len) => { out.push(16); out.extend_from_slice(&len.to_le_bytes()); }
+            FlatInstr::Push => out.push(17),
+            FlatInstr::Pop => out.push(18),
+            FlatInstr::JumpIfZero(target) => { out.push(10); out.extend_from_slice(&(*target as u32).to_le_bytes()); }
+            FlatInstr::JumpIfNonZero(target) => { out.push(11); out.extend_from_slice(&(*target as u32).to_le_bytes()); }
+        }
+    }
+
+    out
+}
+
+/// reads little endian values out of a byte slice
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let end = self.position + N;
+        let taken = self.bytes.get(self.position..end).ok_or(DecodeError::Truncated)?;
+        self.position = end;
+        Ok(taken.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, DecodeError> {
+        Ok(i32::from_le_bytes(self.take()?))
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<FlatInstr>, DecodeError> {
+    if !is_bytecode(bytes) {
+        return Err(DecodeError::NotBytecode);
+    }
+    let mut reader = Reader { bytes, position: MAGIC.len() };
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+
+    let len = reader.u32()? as usize;
+    // don't trust the length for the allocation, every instruction is at least one byte
+    let mut program = Vec::with_capacity(len.min(bytes.len()));
+    // the `JumpIfZero`s around the next instruction and where their loops end,
+    // innermost last. an `If` has no `JumpIfNonZero`, it just ends at its target
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    for index in 0..len {
+        while open.last().is_some_and(|&(_, end)| end <= index) {
+            open.pop();
+        }
+        let at = reader.position;
+        let instr = match reader.u8()? {
+            0 => FlatInstr::Move(reader.i32()?),
+            1 => FlatInstr::Add(reader.u32()?),
+            2 => FlatInstr::Sub(reader.u32()?),
+            3 => FlatInstr::Write,
+            4 => FlatInstr::Read,
+            5 => FlatInstr::ResetCell,
+            6 => FlatInstr::ScanCells(reader.u8()? != 0),
+            7 => {
+                let count = reader.u32()? as usize;
+                let mut targets = Vec::with_capacity(count.min(bytes.len()));
+                for _ in 0..count {
+                    targets.push((reader.i32()?, reader.u32()?));
+                }
+                FlatInstr::MultiplyMove(targets)
+            }
+            8 => FlatInstr::AddAt(reader.i32()?, reader.u32()?),
+            9 => FlatInstr::TapeState,
+            10 => FlatInstr::JumpIfZero(reader.u32()? as usize),
+            11 => FlatInstr::JumpIfNonZero(reader.u32()? as usize),
+            12 => FlatInstr::TapeWindow,
+            13 => FlatInstr::MoveCell(reader.i32()?),
+            14 => FlatInstr::SetCell(reader.u32()?),
+            15 => FlatInstr::InfiniteLoop,
+            16 => match reader.u32()? {
+                0 => return Err(DecodeError::EmptyClearRange(at)),
+                cells => FlatInstr::ClearRange(cells),
+            },
+            17 => FlatInstr::Push,
+            18 => FlatInstr::Pop,
+            tag => return Err(DecodeError::UnknownTag(tag, at)),
+        };
+        match instr {
+            // it has to land past itself and end inside the loop around it
+            FlatInstr::JumpIfZero(target) => {
+                if target <= index || target > open.last().map_or(len, |&(_, end)| end) {
+                    return Err(DecodeError::BadJump(at));
+                }
+                open.push((index, target));
+            }
+            // it has to go back to just after the innermost `JumpIfZero`, which jumps to just after it
+            FlatInstr::JumpIfNonZero(target) if open.last() != Some(&(target.wrapping_sub(1), index + 1)) => {
+                return Err(DecodeError::BadJump(at));
+            }
+            _ => (),
+        }
+        program.push(instr);
+    }
+
+    Ok(program)
+}
@@ -0,0 +1,130 @@
+//! an interactive debugger that runs a flattened program one instruction
+//! at a time, reading commands line by line:
+//!
+//! - `s`, or an empty line, runs the next instruction
+//! - `c` runs until the next breakpoint or the end of the program
+//! - `p` prints the cells around the data pointer
+//! - `b <index>` sets or clears a breakpoint on an instruction, using the
+//!   indices that the `dump` command prints. `b <line>:<column>` does the
+//!   same for the instruction that came from that spot in the source
+//! - `q` stops the program
+//!
+//! given a source map, it also shows where in the source every instruction came from.
+
+use std::collections::BTreeSet;
+use std::io::{BufRead, Read, Write};
+
+use crate::{check_limits, output_error, step, write_tape_window, Cell, FlatInstr, Options, Position, RuntimeError, TAPE_WINDOW_RADIUS};
+
+const HELP: &str = "commands: s (step), c (continue), p (print tape), b <index> or b <line>:<column> (toggle breakpoint), q (quit)";
+
+/// reads commands from `commands` and writes everything it has to say to
+/// `log`, so it doesn't get mixed up with the program's own output
+pub struct Debugger<B: BufRead, L: Write> {
+    commands: B,
+    log: L,
+    breakpoints: BTreeSet<usize>,
+    source_map: Vec<Position>,
+}
+
+impl<B: BufRead, L: Write> Debugger<B, L> {
+    pub fn new(commands: B, log: L) -> Self {
+        Debugger { commands, log, breakpoints: BTreeSet::new(), source_map: Vec::new() }
+    }
+
+    /// the source position of every instruction, as `tokenize_with_positions` keeps track of them
+    pub fn with_source_map(mut self, source_map: Vec<Position>) -> Self {
+        self.source_map = source_map;
+        self
+    }
+
+    /// runs `program` like `execute` does, but stops before every instruction
+    /// and waits for a command. running out of commands stops the program like `q` does.
+    /// `options.max_steps` and `options.timeout` count from the start, waiting for commands included
+    pub fn run<C: Cell, R: Read, W: Write>(&mut self, program: &[FlatInstr], tape: &mut [C], data_pointer: &mut usize, input: &mut R, output: &mut W, options: &Options) -> Result<(), RuntimeError> {
+        let mut instr_pointer = 0;
+        let mut stack = Vec::new();
+        let mut line = String::new();
+        let mut steps = 0;
+        let deadline = options.timeout.map(|timeout| std::time::Instant::now() + timeout);
+
+        self.say(format_args!("{}", HELP));
+        while let Some(instr) = program.get(instr_pointer) {
+            match self.source_map.get(instr_pointer) {
+                Some(at) => write!(self.log, "{:04} {:?} at {}\n> ", instr_pointer, instr, at),
+                None => write!(self.log, "{:04} {:?}\n> ", instr_pointer, instr),
+            }.expect("FAILED TO WRITE DEBUGGER OUTPUT!");
+            self.log.flush().expect("FAILED TO FLUSH DEBUGGER OUTPUT!");
+
+            line.clear();
+            if self.commands.read_line(&mut line).expect("FAILED TO READ DEBUGGER COMMAND!") == 0 {
+                return Ok(());
+            }
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (None, _) | (Some("s"), None) => {
+                    check_limits(options, steps, instr_pointer, deadline)?;
+                    steps += 1;
+                    step(program, &mut instr_pointer, tape, data_pointer, &mut stack, input, output, options)?;
+                }
+                (Some("c"), None) => {
+                    // always run at least one instruction, otherwise the breakpoint
+                    // we're stopped at would keep us there forever
+                    check_limits(options, steps, instr_pointer, deadline)?;
+                    steps += 1;
+                    step(program, &mut instr_pointer, tape, data_pointer, &mut stack, input, output, options)?;
+                    while instr_pointer < program.len() && !self.breakpoints.contains(&instr_pointer) {
+                        check_limits(options, steps, instr_pointer, deadline)?;
+                        steps += 1;
+                        step(program, &mut instr_pointer, tape, data_pointer, &mut stack, input, output, options)?;
+                    }
+                }
+                (Some("p"), None) => {
+                    self.say(format_args!("data pointer: {}", data_pointer));
+                    write_tape_window(tape, *data_pointer, TAPE_WINDOW_RADIUS, &mut self.log).expect("FAILED TO WRITE DEBUGGER OUTPUT!");
+                }
+                (Some("b"), Some(target)) if words.next().is_none() => match self.breakpoint_index(target, program.len()) {
+                    Ok(index) => {
+                        if self.breakpoints.insert(index) {
+                            self.say(format_args!("breakpoint set at {:04}", index));
+                        } else {
+                            self.breakpoints.remove(&index);
+                            self.say(format_args!("breakpoint cleared at {:04}", index));
+                        }
+                    }
+                    Err(message) => self.say(format_args!("{}", message)),
+                },
+                (Some("q"), None) => return Ok(()),
+                _ => self.say(format_args!("{}", HELP)),
+            }
+            output.flush().map_err(output_error)?;
+        }
+
+        self.say(format_args!("program finished"));
+        Ok(())
+    }
+
+    /// the instruction `target` points to, either an index or a
+    /// `<line>:<column>` that the source map has an instruction for
+    fn breakpoint_index(&self, target: &str, program_len: usize) -> Result<usize, String> {
+        let (line, column) = match target.split_once(':') {
+            Some(position) => position,
+            None => return match target.parse::<usize>() {
+                Ok(index) if index < program_len => Ok(index),
+                _ => Err(format!("no instruction at '{}', the program has {}", target, program_len)),
+            },
+        };
+        let at = match (line.parse(), column.parse()) {
+            (Ok(line), Ok(column)) => Position { line, column },
+            _ => return Err(format!("'{}' isn't a line and column", target)),
+        };
+        match self.source_map.iter().position(|position| *position == at) {
+            Some(index) if index < program_len => Ok(index),
+            _ => Err(format!("no instruction at {}", at)),
+        }
+    }
+
+    fn say(&mut self, message: std::fmt::Arguments) {
+        writeln!(self.log, "{}", message).expect("FAILED TO WRITE DEBUGGER OUTPUT!");
+    }
+}
@@ -0,0 +1,188 @@
+//! puts brainfuck source back together with one layout, so programs are
+//! easier to read and to diff. only the symbols and the comments between
+//! them matter, the program is never run.
+
+use crate::{tokenize_with_comments, Lexeme, OpCode};
+
+/// how far every loop level is indented
+const INDENT: &str = "  ";
+
+/// the source with every loop that holds another loop split over several
+/// lines, and its body indented by one level. loops without loops inside
+/// stay on one line, like `[->+<]`. with `comments` the text between symbols
+/// is kept: a comment stays on the line it was on, and its lines that have
+/// nothing else on them are kept too, with at most one empty line in a row.
+/// without it, everything but the symbols is dropped. formatting the result
+/// again doesn't change it.
+pub fn format_source(source: &str, comments: bool) -> String {
+    let lexemes: Vec<Lexeme> = tokenize_with_comments(source).into_iter()
+        .filter(|lexeme| comments || matches!(lexeme, Lexeme::Op(..)))
+        .collect();
+    let split = split_loops(&lexemes);
+
+    let mut out = Lines::default();
+    // the loops that are open, and whether they were split
+    let mut open = Vec::new();
+    for (i, lexeme) in lexemes.iter().enumerate() {
+        match lexeme {
+            Lexeme::Op(OpCode::LoopBegin, _) => {
+                out.push_code("[");
+                if split[i] {
+                    out.break_line();
+                    out.depth += 1;
+                }
+                open.push(split[i]);
+            }
+            // a stray `]` is kept where it is
+            Lexeme::Op(OpCode::LoopEnd, _) => if open.pop() == Some(true) {
+                out.end_line();
+                out.depth -= 1;
+                out.push_code("]");
+                out.break_line();
+            } else {
+                out.push_code("]");
+            },
+            Lexeme::Op(op, _) => out.push_code(symbol(op)),
+            Lexeme::Comment(text, _) => {
+                let mut lines = text.split('\n');
+                out.push_comment(lines.next().unwrap_or(""));
+                let mut rest: Vec<&str> = lines.collect();
+                let last = rest.pop();
+                for line in rest {
+                    out.end_line();
+                    if line.trim().is_empty() {
+                        out.blank_line();
+                    }
+                    out.push_comment(line);
+                }
+                // what comes after the last newline starts the next line
+                if let Some(line) = last {
+                    out.end_line();
+                    out.push_comment(line);
+                }
+            }
+        }
+    }
+    out.end_line();
+    if out.lines.last().is_some_and(|line| line.is_empty()) {
+        out.lines.pop();
+    }
+
+    out.lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+/// for every `[` in `lexemes`, whether its loop goes over several lines:
+/// if there's another loop or a line break in it, or it's never closed
+fn split_loops(lexemes: &[Lexeme]) -> Vec<bool> {
+    let mut split = vec![false; lexemes.len()];
+    let mut open: Vec<usize> = Vec::new();
+    for (i, lexeme) in lexemes.iter().enumerate() {
+        match lexeme {
+            Lexeme::Op(OpCode::LoopBegin, _) => {
+                if let Some(&outer) = open.last() {
+                    split[outer] = true;
+                }
+                open.push(i);
+            }
+            Lexeme::Op(OpCode::LoopEnd, _) => {
+                open.pop();
+            }
+            Lexeme::Comment(text, _) if text.contains('\n') => {
+                if let Some(&outer) = open.last() {
+                    split[outer] = true;
+                }
+            }
+            _ => (),
+        }
+    }
+    for i in open {
+        split[i] = true;
+    }
+    split
+}
+
+fn symbol(op: &OpCode) -> &'static str {
+    match op {
+        OpCode::IncrementPointer => ">",
+        OpCode::DecrementPointer => "<",
+        OpCode::Increment => "+",
+        OpCode::Decrement => "-",
+        OpCode::Write => ".",
+        OpCode::Read => ",",
+        OpCode::LoopBegin => "[",
+        OpCode::LoopEnd => "]",
+        OpCode::TapeState => "|",
+        OpCode::TapeWindow => "#",
+        OpCode::Push => ":",
+        OpCode::Pop => ";",
+        _ => unreachable!("THE TOKENIZER ONLY MAKES SYMBOLS!"),
+    }
+}
+
+/// the lines written so far, and the one being written
+#[derive(Default)]
+struct Lines {
+    lines: Vec<String>,
+    line: String,
+    /// how far the line being written is indented
+    line_depth: usize,
+    depth: usize,
+    /// set when the next symbol has to go on a new line, which only a
+    /// comment may still be put before
+    broken: bool,
+    /// whether a comment was the last thing put on the line
+    after_comment: bool,
+}
+
+impl Lines {
+    fn push_code(&mut self, code: &str) {
+        if self.broken {
+            self.end_line();
+        }
+        if self.after_comment {
+            self.line.push(' ');
+        }
+        self.push(code);
+        self.after_comment = false;
+    }
+
+    fn push_comment(&mut self, comment: &str) {
+        let comment = comment.trim();
+        if comment.is_empty() {
+            return;
+        }
+        if !self.line.is_empty() {
+            self.line.push(' ');
+        }
+        self.push(comment);
+        self.after_comment = true;
+    }
+
+    fn push(&mut self, text: &str) {
+        if self.line.is_empty() {
+            self.line_depth = self.depth;
+        }
+        self.line.push_str(text);
+    }
+
+    fn break_line(&mut self) {
+        self.broken = true;
+    }
+
+    /// finishes the line being written, if anything was written to it
+    fn end_line(&mut self) {
+        self.broken = false;
+        self.after_comment = false;
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            self.lines.push(format!("{}{}", INDENT.repeat(self.line_depth), line));
+        }
+    }
+
+    /// adds an empty line, unless there's one already
+    fn blank_line(&mut self) {
+        if self.lines.last().is_some_and(|line| !line.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+}
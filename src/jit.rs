@@ -0,0 +1,254 @@
+//! compiles flattened programs to x86_64 machine code at runtime, with the
+//! `jit` feature. there's no code generator to lean on, so the instructions
+//! are encoded by hand, and the memory to run them from comes straight from
+//! `mmap`. only the instructions that do arithmetic, move the pointer or jump
+//! become machine code. everything else, like `Read`, `Write` and scans,
+//! calls back into `step` for that one instruction, so it does exactly what
+//! the interpreter would.
+//!
+//! the generated code keeps the data pointer in `rbx`, the context in `r12`,
+//! the start of the tape in `r13` and the tape's length in `r14`.
+
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+compile_error!("the jit only knows how to write x86_64 code for linux");
+
+use std::ffi::c_void;
+use std::io::{self, Read, Write};
+
+use crate::{step, Cell, CellOverflow, FlatInstr, Options, RuntimeError, TapeMode};
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 2;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut c_void;
+    fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+}
+
+/// what the generated code is called as: the context, the start of the
+/// tape, the data pointer and the tape's length. it returns 0 once the
+/// program ends, and 1 when a call back into `step` failed
+type Entry = unsafe extern "C" fn(*mut c_void, *mut u8, usize, usize) -> u32;
+
+/// a program compiled to machine code for a tape of one length
+pub struct JitProgram {
+    program: Vec<FlatInstr>,
+    tape_len: usize,
+    code: *mut c_void,
+    code_len: usize,
+}
+
+impl JitProgram {
+    /// compiles `program` for a tape of `tape_len` cells. moves and offsets
+    /// as long as the whole tape or longer go through `step` too, the
+    /// generated code only wraps around the tape once
+    pub fn compile(program: &[FlatInstr], tape_len: usize) -> io::Result<JitProgram> {
+        let code = assemble(program, tape_len);
+        let code_len = code.len();
+        // SAFETY: asks for fresh memory, and only writes as much as was asked for
+        unsafe {
+            let memory = mmap(std::ptr::null_mut(), code_len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+            if memory as isize == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            std::ptr::copy_nonoverlapping(code.as_ptr(), memory as *mut u8, code.len());
+            if mprotect(memory, code_len, PROT_READ | PROT_EXEC) != 0 {
+                let e = io::Error::last_os_error();
+                munmap(memory, code_len);
+                return Err(e);
+            }
+            Ok(JitProgram { program: program.to_vec(), tape_len, code: memory, code_len })
+        }
+    }
+
+    /// runs the program like `execute` would. cells have to be 8 bits, and
+    /// the tape as long as the one the program was compiled for. the tape
+    /// and the cells wrap around, `max_steps`, `timeout` and `interrupt`
+    /// aren't checked, and the other options only change what the calls
+    /// back into `step` do
+    pub fn run<C: Cell, R: Read, W: Write>(&self, tape: &mut [C], data_pointer: &mut usize, input: &mut R, output: &mut W, options: &Options) -> Result<(), RuntimeError> {
+        assert_eq!(std::mem::size_of::<C>(), 1, "THE JIT ONLY KNOWS 8 BIT CELLS!");
+        assert_eq!(tape.len(), self.tape_len, "THE TAPE ISN'T THE LENGTH THE PROGRAM WAS COMPILED FOR!");
+        assert!(*data_pointer < tape.len(), "THE DATA POINTER HAS TO BE ON THE TAPE!");
+        assert!(options.tape_mode == TapeMode::Wrap && options.cell_overflow == CellOverflow::Wrap, "THE JIT ONLY WRAPS!");
+
+        let mut context = Context {
+            data_pointer: *data_pointer,
+            step: step_one::<C, R, W>,
+            program: &self.program,
+            tape: tape.as_mut_ptr(),
+            tape_len: tape.len(),
+            stack: Vec::new(),
+            input,
+            output,
+            options,
+            error: None,
+        };
+        // SAFETY: the code only touches the tape through `r13` and indexes
+        // it below `r14`, and the context is alive for the whole call
+        let status = unsafe {
+            let entry: Entry = std::mem::transmute(self.code);
+            entry(&mut context as *mut Context<C, R, W> as *mut c_void, tape.as_mut_ptr() as *mut u8, *data_pointer, tape.len())
+        };
+        *data_pointer = context.data_pointer;
+        match (status, context.error) {
+            (0, _) => Ok(()),
+            (_, Some(e)) => Err(e),
+            (_, None) => unreachable!("A FAILED STEP ALWAYS LEAVES AN ERROR!"),
+        }
+    }
+}
+
+impl Drop for JitProgram {
+    fn drop(&mut self) {
+        // SAFETY: the memory came from `mmap` with this length, and nothing runs it anymore
+        unsafe {
+            munmap(self.code, self.code_len);
+        }
+    }
+}
+
+/// what the calls back into `step` need. the generated code reads and
+/// writes `data_pointer` and calls `step`, so those two come first and
+/// have to stay where they are
+#[repr(C)]
+struct Context<'a, C: Cell, R, W> {
+    data_pointer: usize,
+    step: unsafe extern "C" fn(*mut Context<'a, C, R, W>, u32) -> u32,
+    program: &'a [FlatInstr],
+    tape: *mut C,
+    tape_len: usize,
+    stack: Vec<C>,
+    input: &'a mut R,
+    output: &'a mut W,
+    options: &'a Options,
+    error: Option<RuntimeError>,
+}
+
+/// runs the instruction at `at` with `step`, for the instructions that
+/// aren't machine code. returns 1 and keeps the error if it failed
+unsafe extern "C" fn step_one<C: Cell, R: Read, W: Write>(context: *mut Context<C, R, W>, at: u32) -> u32 {
+    let context = &mut *context;
+    let tape = std::slice::from_raw_parts_mut(context.tape, context.tape_len);
+    let mut instr_pointer = at as usize;
+    match step(context.program, &mut instr_pointer, tape, &mut context.data_pointer, &mut context.stack, context.input, context.output, context.options) {
+        Ok(_) => 0,
+        Err(e) => {
+            context.error = Some(e);
+            1
+        }
+    }
+}
+
+/// the machine code for `program`, see the module docs for the registers
+fn assemble(program: &[FlatInstr], tape_len: usize) -> Vec<u8> {
+    let mut code = Vec::new();
+    // push rbx, r12, r13, r14 and r15, which also lines the stack up for calls
+    code.extend_from_slice(&[0x53, 0x41, 0x54, 0x41, 0x55, 0x41, 0x56, 0x41, 0x57]);
+    // mov r12, rdi; mov r13, rsi; mov rbx, rdx; mov r14, rcx
+    code.extend_from_slice(&[0x49, 0x89, 0xfc, 0x49, 0x89, 0xf5, 0x48, 0x89, 0xd3, 0x49, 0x89, 0xce]);
+
+    // where every instruction starts, and the jumps still waiting for them
+    let mut starts = Vec::with_capacity(program.len() + 1);
+    let mut jumps: Vec<(usize, usize)> = Vec::new();
+    let mut exits: Vec<usize> = Vec::new();
+    let fits = |offset: i32| (offset as i64).unsigned_abs() < tape_len as u64;
+    for (at, instr) in program.iter().enumerate() {
+        starts.push(code.len());
+        match instr {
+            FlatInstr::Add(count) => add_cell(&mut code, *count as u8),
+            FlatInstr::Sub(count) => add_cell(&mut code, (*count as u8).wrapping_neg()),
+            // mov byte [r13 + rbx], value
+            FlatInstr::SetCell(value) => code.extend_from_slice(&[0x41, 0xc6, 0x44, 0x1d, 0x00, *value as u8]),
+            FlatInstr::ResetCell => code.extend_from_slice(&[0x41, 0xc6, 0x44, 0x1d, 0x00, 0x00]),
+            FlatInstr::Move(offset) if fits(*offset) => {
+                // add rbx, offset, and back onto the tape with r14: cmp rbx, r14; jb past it
+                code.extend_from_slice(&[0x48, 0x81, 0xc3]);
+                code.extend_from_slice(&offset.to_le_bytes());
+                code.extend_from_slice(&[0x4c, 0x39, 0xf3, 0x72, 0x03]);
+                // sub rbx, r14 past the end, add rbx, r14 before the start
+                code.extend_from_slice(&[0x4c, if *offset > 0 { 0x29 } else { 0x01 }, 0xf3]);
+            }
+            FlatInstr::AddAt(offset, count) if fits(*offset) => {
+                offset_into_rcx(&mut code, *offset);
+                // add byte [r13 + rcx], count
+                code.extend_from_slice(&[0x41, 0x80, 0x44, 0x0d, 0x00, *count as u8]);
+            }
+            FlatInstr::MoveCell(offset) if fits(*offset) => multiply_move(&mut code, &[(*offset, 1)]),
+            FlatInstr::MultiplyMove(targets) if targets.iter().all(|(offset, _)| fits(*offset)) => multiply_move(&mut code, targets),
+            FlatInstr::JumpIfZero(target) | FlatInstr::JumpIfNonZero(target) => {
+                // cmp byte [r13 + rbx], 0; je or jne to the target
+                code.extend_from_slice(&[0x41, 0x80, 0x7c, 0x1d, 0x00, 0x00, 0x0f]);
+                code.push(if matches!(instr, FlatInstr::JumpIfZero(_)) { 0x84 } else { 0x85 });
+                jumps.push((code.len(), *target));
+                code.extend_from_slice(&[0; 4]);
+            }
+            _ => {
+                // mov [r12], rbx; mov rdi, r12; mov esi, at; call [r12 + 8]
+                code.extend_from_slice(&[0x49, 0x89, 0x1c, 0x24, 0x4c, 0x89, 0xe7, 0xbe]);
+                code.extend_from_slice(&(at as u32).to_le_bytes());
+                code.extend_from_slice(&[0x41, 0xff, 0x54, 0x24, 0x08]);
+                // test eax, eax; jnz to the exit
+                code.extend_from_slice(&[0x85, 0xc0, 0x0f, 0x85]);
+                exits.push(code.len());
+                code.extend_from_slice(&[0; 4]);
+                // mov rbx, [r12], `step` might have moved it
+                code.extend_from_slice(&[0x49, 0x8b, 0x1c, 0x24]);
+            }
+        }
+    }
+    starts.push(code.len());
+    // mov [r12], rbx; xor eax, eax
+    code.extend_from_slice(&[0x49, 0x89, 0x1c, 0x24, 0x31, 0xc0]);
+    let exit = code.len();
+    // pop them back in reverse, and ret
+    code.extend_from_slice(&[0x41, 0x5f, 0x41, 0x5e, 0x41, 0x5d, 0x41, 0x5c, 0x5b, 0xc3]);
+
+    // jumps count from the end of their 4 bytes
+    let mut patch = |at: usize, to: usize| {
+        let relative = to as i64 - (at as i64 + 4);
+        code[at..at + 4].copy_from_slice(&(relative as i32).to_le_bytes());
+    };
+    for (at, target) in jumps {
+        patch(at, starts[target]);
+    }
+    for at in exits {
+        patch(at, exit);
+    }
+    code
+}
+
+/// add byte [r13 + rbx], count
+fn add_cell(code: &mut Vec<u8>, count: u8) {
+    code.extend_from_slice(&[0x41, 0x80, 0x44, 0x1d, 0x00, count]);
+}
+
+/// puts the index of the cell `offset` away from the data pointer in `rcx`,
+/// wrapped around the tape once
+fn offset_into_rcx(code: &mut Vec<u8>, offset: i32) {
+    // mov rcx, rbx; add rcx, offset
+    code.extend_from_slice(&[0x48, 0x89, 0xd9, 0x48, 0x81, 0xc1]);
+    code.extend_from_slice(&offset.to_le_bytes());
+    // cmp rcx, r14; jb past it; sub or add rcx, r14
+    code.extend_from_slice(&[0x4c, 0x39, 0xf1, 0x72, 0x03, 0x4c, if offset > 0 { 0x29 } else { 0x01 }, 0xf1]);
+}
+
+/// adds the current cell times each factor to its target, then clears it
+fn multiply_move(code: &mut Vec<u8>, targets: &[(i32, u32)]) {
+    // movzx eax, byte [r13 + rbx]
+    code.extend_from_slice(&[0x41, 0x0f, 0xb6, 0x44, 0x1d, 0x00]);
+    for (offset, factor) in targets {
+        offset_into_rcx(code, *offset);
+        // mov edx, eax; imul edx, edx, factor
+        code.extend_from_slice(&[0x89, 0xc2, 0x69, 0xd2]);
+        code.extend_from_slice(&factor.to_le_bytes());
+        // add byte [r13 + rcx], dl
+        code.extend_from_slice(&[0x41, 0x00, 0x54, 0x0d, 0x00]);
+    }
+    // mov byte [r13 + rbx], 0
+    code.extend_from_slice(&[0x41, 0xc6, 0x44, 0x1d, 0x00, 0x00]);
+}
@@ -0,0 +1,1979 @@
+//! brainfuck tokenizer, optimizer, parser and interpreter.
+//! `main.rs` is only a thin command line wrapper around this.
+
+use std::io::{Read, Write};
+use std::ops::ControlFlow;
+
+pub mod bytecode;
+pub mod debugger;
+pub mod format;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod repl;
+pub mod state;
+pub mod transpile;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OpCode {
+    Move(i32),
+    IncrementPointer, DecrementPointer,
+    Increment, Decrement,
+    Add(u32), Sub(u32),
+    Write, Read,
+    LoopBegin, LoopEnd,
+    ResetCell,
+    SetCell(u32),
+    ScanCells(bool),
+    MultiplyMove(Vec<(i32, u32)>),
+    MoveCell(i32),
+    AddAt(i32, u32),
+    TapeState,
+    TapeWindow,
+    InfiniteLoop,
+    ClearRange(u32),
+    Push, Pop,
+    /// closes a loop that can only run once, see `if_loops`
+    IfEnd,
+}
+
+#[derive(Clone, Debug)]
+pub enum Instruction {
+    Move(i32),
+    Add(u32), Sub(u32),
+    Write, Read,
+    Loop(Vec<Instruction>),
+    /// a loop whose body always leaves the current cell at zero, so it
+    /// runs once if the cell isn't zero and not at all if it is
+    If(Vec<Instruction>),
+    ResetCell,
+    /// sets the current cell to a value, e.g. for `[-]+++`
+    SetCell(u32),
+    ScanCells(bool),
+    /// adds the current cell times each multiplier to the cell at each
+    /// offset, then clears the current cell
+    MultiplyMove(Vec<(i32, u32)>),
+    /// adds the current cell to the cell at an offset and clears it,
+    /// the common case of `MultiplyMove` with one target and multiplier 1
+    MoveCell(i32),
+    /// adds to the cell at an offset from the data pointer without moving it
+    AddAt(i32, u32),
+    TapeState,
+    /// prints the cells around the data pointer, see `write_tape_window`
+    TapeWindow,
+    /// an empty loop `[]`, which does nothing on a zero cell and would
+    /// never end on any other
+    InfiniteLoop,
+    /// clears this many cells from the current one to the right and leaves
+    /// the pointer on the last one, for `[-]>[-]>[-]`
+    ClearRange(u32),
+    /// pushes the current cell onto the stack, for `:` with the `stack` feature
+    Push,
+    /// pops the stack into the current cell, for `;` with the `stack` feature
+    Pop,
+}
+
+/// a flattened `Instruction`, see `flatten`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FlatInstr {
+    Move(i32),
+    Add(u32), Sub(u32),
+    Write, Read,
+    ResetCell,
+    SetCell(u32),
+    ScanCells(bool),
+    MultiplyMove(Vec<(i32, u32)>),
+    MoveCell(i32),
+    AddAt(i32, u32),
+    TapeState,
+    TapeWindow,
+    InfiniteLoop,
+    ClearRange(u32),
+    Push, Pop,
+    /// jumps to the index if the current cell is zero
+    JumpIfZero(usize),
+    /// jumps to the index if the current cell isn't zero
+    JumpIfNonZero(usize),
+}
+
+impl FlatInstr {
+    /// the variant name, without any operands
+    pub fn name(&self) -> &'static str {
+        match self {
+            FlatInstr::Move(_) => "Move",
+            FlatInstr::Add(_) => "Add",
+            FlatInstr::Sub(_) => "Sub",
+            FlatInstr::Write => "Write",
+            FlatInstr::Read => "Read",
+            FlatInstr::ResetCell => "ResetCell",
+            FlatInstr::SetCell(_) => "SetCell",
+            FlatInstr::ScanCells(_) => "ScanCells",
+            FlatInstr::MultiplyMove(_) => "MultiplyMove",
+            FlatInstr::MoveCell(_) => "MoveCell",
+            FlatInstr::AddAt(_, _) => "AddAt",
+            FlatInstr::TapeState => "TapeState",
+            FlatInstr::TapeWindow => "TapeWindow",
+            FlatInstr::InfiniteLoop => "InfiniteLoop",
+            FlatInstr::ClearRange(_) => "ClearRange",
+            FlatInstr::Push => "Push",
+            FlatInstr::Pop => "Pop",
+            FlatInstr::JumpIfZero(_) => "JumpIfZero",
+            FlatInstr::JumpIfNonZero(_) => "JumpIfNonZero",
+        }
+    }
+}
+
+/// how many times each kind of instruction was executed. a loop shows up as
+/// one `JumpIfZero` per entry and one `JumpIfNonZero` per iteration.
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    counts: std::collections::BTreeMap<&'static str, u64>,
+    /// iterations by the index of the `JumpIfZero` that opens the loop
+    loops: std::collections::BTreeMap<usize, u64>,
+    /// for every loop, how many of its entries ran the body how many times.
+    /// the entry that's still going isn't in here yet
+    entries: std::collections::BTreeMap<usize, std::collections::BTreeMap<u64, u64>>,
+    /// how many times the body ran since each loop was last entered
+    running: std::collections::BTreeMap<usize, u64>,
+    highest_cell: usize,
+}
+
+impl Stats {
+    /// notes that `instr`, at index `at` of the program, is about to run
+    pub fn record(&mut self, at: usize, instr: &FlatInstr) {
+        *self.counts.entry(instr.name()).or_insert(0) += 1;
+        match instr {
+            // a loop can't be entered again before its last entry is done
+            FlatInstr::JumpIfZero(_) => if let Some(iterations) = self.running.insert(at, 0) {
+                *self.entries.entry(at).or_default().entry(iterations).or_insert(0) += 1;
+            },
+            // every run through the body ends in the `JumpIfNonZero`, which points just past the `JumpIfZero`
+            FlatInstr::JumpIfNonZero(target) => {
+                *self.loops.entry(target - 1).or_insert(0) += 1;
+                *self.running.entry(target - 1).or_insert(0) += 1;
+            }
+            _ => (),
+        }
+    }
+
+    /// notes the cells `instr` used, after it ran with the data pointer at
+    /// `before` and left it at `after`
+    pub(crate) fn record_cells(&mut self, instr: &FlatInstr, before: usize, after: usize, tape_len: usize, mode: TapeMode) {
+        let reached = |offset: i32| offset_index(before, offset, tape_len, mode).unwrap_or(before);
+        let highest = match instr {
+            FlatInstr::AddAt(offset, _) | FlatInstr::MoveCell(offset) => reached(*offset),
+            FlatInstr::MultiplyMove(targets) => targets.iter().map(|(offset, _)| reached(*offset)).max().unwrap_or(before),
+            // the pointer ends up before the end of the range if it wrapped around
+            FlatInstr::ClearRange(len) if before + *len as usize > tape_len => tape_len - 1,
+            _ => before,
+        };
+        self.highest_cell = self.highest_cell.max(highest).max(after);
+    }
+
+    /// the index of the highest cell the program used, either with the data
+    /// pointer or through an offset, to see how much of the tape it needs
+    pub fn highest_cell(&self) -> usize {
+        self.highest_cell
+    }
+
+    /// how many times the body ran of the loop opened by the `JumpIfZero` at `start`
+    pub fn loop_iterations(&self, start: usize) -> u64 {
+        self.loops.get(&start).copied().unwrap_or(0)
+    }
+
+    /// up to `count` of the loops whose body ran the most times, busiest
+    /// first, as the index of their `JumpIfZero` and how often the body ran
+    pub fn hottest_loops(&self, count: usize) -> Vec<(usize, u64)> {
+        let mut loops: Vec<(usize, u64)> = self.loops.iter().map(|(start, iterations)| (*start, *iterations)).collect();
+        loops.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        loops.truncate(count);
+        loops
+    }
+
+    /// how many times the loop opened by the `JumpIfZero` at `start` was
+    /// entered with its body then running each number of times, as
+    /// `(iterations, entries)` with the fewest iterations first
+    pub fn loop_histogram(&self, start: usize) -> Vec<(u64, u64)> {
+        let mut histogram = self.entries.get(&start).cloned().unwrap_or_default();
+        if let Some(iterations) = self.running.get(&start) {
+            *histogram.entry(*iterations).or_insert(0) += 1;
+        }
+        histogram.into_iter().collect()
+    }
+
+    /// how many times instructions named `name` ran
+    pub fn count(&self, name: &str) -> u64 {
+        self.counts.get(name).copied().unwrap_or(0)
+    }
+
+    /// total number of instructions retired
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total = self.total();
+        writeln!(f, "instructions retired: {}", total)?;
+        let mut counts: Vec<_> = self.counts.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1));
+        for (name, count) in counts {
+            writeln!(f, "  {:<12} {:>12} ({:.1}%)", name, count, *count as f64 * 100.0 / total as f64)?;
+        }
+        Ok(())
+    }
+}
+
+/// what `Read` does to the current cell once the input runs out
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum EofBehavior {
+    /// leave the cell as it was
+    #[default]
+    Unchanged,
+    /// set the cell to 0
+    Zero,
+    /// set the cell to -1, i.e. every bit set (255 for 8 bit cells)
+    NegOne,
+    /// stop with `RuntimeError::InputExhausted`, for programs that should
+    /// read exactly the input they're given and no more
+    Error,
+}
+
+/// what `+` and `-` do at the ends of what a cell can hold
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CellOverflow {
+    /// go around to the other end, so 255 + 1 is 0 with 8 bit cells
+    #[default]
+    Wrap,
+    /// stay at 0 or at the highest value. the optimizer merges `+` and `-`
+    /// together, which only adds up when they wrap, so this only matches
+    /// the source without `SATURATE_UNSAFE` passes
+    Saturate,
+}
+
+/// what happens when the data pointer leaves the tape
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TapeMode {
+    /// come back in at the other end, as if the tape was a ring
+    #[default]
+    Wrap,
+    /// stay on the first or last cell. the optimizer folds moves in
+    /// opposite directions together, which only adds up when they wrap, so
+    /// this only matches the source without `CLAMP_UNSAFE` passes
+    Clamp,
+    /// stop with `RuntimeError::PointerOutOfBounds`. merged moves only go
+    /// to where they end up, and would skip a trip off the tape and back
+    /// like `<>` on the first cell, so this also only matches the source
+    /// without `CLAMP_UNSAFE` passes
+    Error,
+}
+
+/// the passes that merge moves in opposite directions, like `><`, and so
+/// change where the pointer ends up with `TapeMode::Clamp`, or miss it
+/// leaving the tape on the way with `TapeMode::Error`
+pub const CLAMP_UNSAFE: &[Pass] = &[Pass::Multiply, Pass::Moves, Pass::Offsets];
+
+/// the passes that merge `+` and `-` together, like `+-` or `[-]-`, or
+/// count on a cell wrapping around to zero, like `[+]`, and so change what
+/// ends up in a cell with `CellOverflow::Saturate`
+pub const SATURATE_UNSAFE: &[Pass] = &[Pass::Reset, Pass::Multiply, Pass::RunLength, Pass::Set, Pass::KnownZero];
+
+/// the passes that count on the tape being all zeros when the program
+/// starts, which it isn't for a program that runs on a tape another one used
+pub const ZEROED_TAPE_ONLY: &[Pass] = &[Pass::KnownZero];
+
+/// settings that change how `execute` behaves
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// what `Read` does at the end of input
+    pub eof: EofBehavior,
+    /// what happens when the data pointer leaves the tape
+    pub tape_mode: TapeMode,
+    /// what `Add`, `Sub` and `AddAt` do at the ends of what a cell can hold
+    pub cell_overflow: CellOverflow,
+    /// stop with `RuntimeError::StepLimitReached` once this many
+    /// instructions have run, for programs that might never finish
+    pub max_steps: Option<u64>,
+    /// stop with `RuntimeError::TimedOut` once the program has been
+    /// running for longer than this
+    pub timeout: Option<std::time::Duration>,
+    /// make `TapeState` and `TapeWindow` redraw a colored grid of the
+    /// tape instead, see `write_tape_grid`
+    pub visualize: bool,
+    /// with cells wider than 8 bits, make `Read` take a whole utf-8
+    /// encoded character instead of a single byte
+    pub utf8_input: bool,
+    /// make `Write` print the cell as a decimal number followed by a space
+    pub numeric_output: bool,
+    /// make `TapeState` show nonzero cells with their character, like `72='H'`
+    pub ascii_table: bool,
+    /// stop with `RuntimeError::Interrupted` once this is set, e.g. by a
+    /// signal handler
+    pub interrupt: Option<&'static std::sync::atomic::AtomicBool>,
+    /// don't flush the output before every `Read`, for output that nobody
+    /// reads while the program runs, like a file. programs that read and
+    /// write a lot are much faster without it
+    pub buffer_across_reads: bool,
+}
+
+/// a tape cell. counts and multipliers are carried around as `u32` and
+/// truncated to the cell width, so all arithmetic wraps at the cell size.
+pub trait Cell: Copy + Default + PartialEq + std::fmt::Debug + std::fmt::Display + std::fmt::LowerHex {
+    /// every bit set, which is what `EofBehavior::NegOne` stores
+    const MAX: Self;
+
+    /// truncates `value` to the width of the cell
+    fn from_u32(value: u32) -> Self;
+    fn to_u32(self) -> u32;
+    fn wrapping_add(self, other: Self) -> Self;
+    fn wrapping_sub(self, other: Self) -> Self;
+    fn wrapping_mul(self, other: Self) -> Self;
+
+    fn is_zero(self) -> bool {
+        self == Self::default()
+    }
+}
+
+macro_rules! impl_cell {
+    ($($ty:ty),*) => {$(
+        impl Cell for $ty {
+            const MAX: Self = <$ty>::MAX;
+
+            fn from_u32(value: u32) -> Self { value as $ty }
+            fn to_u32(self) -> u32 { self as u32 }
+            fn wrapping_add(self, other: Self) -> Self { <$ty>::wrapping_add(self, other) }
+            fn wrapping_sub(self, other: Self) -> Self { <$ty>::wrapping_sub(self, other) }
+            fn wrapping_mul(self, other: Self) -> Self { <$ty>::wrapping_mul(self, other) }
+        }
+    )*};
+}
+
+impl_cell!(u8, u16, u32);
+
+/// where something is in the source code, both counted from 1.
+/// columns count characters, not bytes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// a bracket that has no partner, with where it was found
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    StrayClosingBracket(Position),
+    StrayOpeningBracket(Position),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::StrayClosingBracket(at) => write!(f, "stray closing bracket at {}", at),
+            ParseError::StrayOpeningBracket(at) => write!(f, "stray opening bracket at {}", at),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// something that went wrong while a program was running
+#[derive(Clone, Debug, PartialEq)]
+pub enum RuntimeError {
+    /// the data pointer left the tape with `TapeMode::Error`, with the
+    /// index of the cell it tried to reach and of the instruction that did it
+    PointerOutOfBounds(i64, usize),
+    /// `Options::max_steps` instructions ran and the program still wasn't
+    /// done, with the index of the instruction that would have run next
+    StepLimitReached(u64, usize),
+    /// the program ran for longer than `Options::timeout`
+    TimedOut(std::time::Duration),
+    /// a `ScanCells` went all the way around the tape without finding a
+    /// zero cell, so the loop it replaced would never have ended
+    NoZeroCell,
+    /// an empty loop ran on a nonzero cell, with the index of its instruction
+    InfiniteLoop(usize),
+    /// `Options::interrupt` was set, after this many instructions and with
+    /// the index of the instruction that would have run next
+    Interrupted(u64, usize),
+    /// reading the input failed for a reason other than running out of it
+    InputError(std::io::ErrorKind),
+    /// writing the output failed, like when it's piped into a program that quit
+    OutputError(std::io::ErrorKind),
+    /// a `Pop` ran with nothing on the stack, with the index of its instruction
+    EmptyStack(usize),
+    /// a `Read` ran out of input with `EofBehavior::Error`, with the index of its instruction
+    InputExhausted(usize),
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeError::PointerOutOfBounds(cell, at) => write!(f, "instruction {:04} moved the data pointer out of bounds to cell #{}", at, cell),
+            RuntimeError::StepLimitReached(steps, at) =>
+                write!(f, "execution truncated after {} instructions, stopped before instruction {:04}", steps, at),
+            RuntimeError::TimedOut(timeout) => write!(f, "execution timed out after {:?}", timeout),
+            RuntimeError::NoZeroCell => write!(f, "scan loop found no zero cell anywhere on the tape and would never end"),
+            RuntimeError::InfiniteLoop(at) => write!(f, "empty loop at instruction {:04} started on a nonzero cell and would never end", at),
+            RuntimeError::Interrupted(steps, at) =>
+                write!(f, "interrupted after {} instructions, stopped before instruction {:04}", steps, at),
+            RuntimeError::InputError(kind) => write!(f, "couldn't read input: {}", kind),
+            RuntimeError::OutputError(kind) => write!(f, "couldn't write output: {}", kind),
+            RuntimeError::EmptyStack(at) => write!(f, "instruction {:04} popped an empty stack", at),
+            RuntimeError::InputExhausted(at) => write!(f, "instruction {:04} read past the end of the input", at),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// anything that can go wrong running a program from source
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    Parse(ParseError),
+    Runtime(RuntimeError),
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        Error::Parse(e)
+    }
+}
+
+impl From<RuntimeError> for Error {
+    fn from(e: RuntimeError) -> Self {
+        Error::Runtime(e)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(e) => e.fmt(f),
+            Error::Runtime(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// this turns the source code into a sequence of opcodes.
+/// should be somewhat easier to work with :3
+pub fn tokenize(source: &str) -> Vec<OpCode> {
+    source.chars().filter_map(symbol_opcode).collect()
+}
+
+/// blanks out `/* ... */` blocks and `;` comments up to the end of the line,
+/// so brainfuck symbols in them aren't run. a block that's never closed runs
+/// to the end of the source. comments turn into spaces and newlines stay,
+/// so positions in the source don't change
+pub fn strip_comments(source: &str) -> String {
+    let mut stripped = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    while let Some(symbol) = chars.next() {
+        match symbol {
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                stripped.push_str("  ");
+                let mut last = ' ';
+                for symbol in chars.by_ref() {
+                    stripped.push(if symbol == '\n' { '\n' } else { ' ' });
+                    if last == '*' && symbol == '/' {
+                        break;
+                    }
+                    last = symbol;
+                }
+            }
+            ';' => {
+                stripped.push(' ');
+                while chars.next_if(|symbol| *symbol != '\n').is_some() {
+                    stripped.push(' ');
+                }
+            }
+            _ => stripped.push(symbol),
+        }
+    }
+    stripped
+}
+
+/// replaces every alias in `source` with the symbol it stands for, e.g. to
+/// run a dialect that spells the commands differently. where two aliases
+/// start at the same place the longer one wins. a longer alias is padded
+/// with spaces, so positions in the source don't change, and the symbols
+/// themselves still mean what they always do
+pub fn remap(source: &str, aliases: &[(String, char)]) -> String {
+    let mut longest_first: Vec<&(String, char)> = aliases.iter().collect();
+    longest_first.sort_by_key(|(alias, _)| std::cmp::Reverse(alias.chars().count()));
+    let mut mapped = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(symbol) = rest.chars().next() {
+        match longest_first.iter().find(|(alias, _)| !alias.is_empty() && rest.starts_with(alias.as_str())) {
+            Some((alias, replacement)) => {
+                mapped.push(*replacement);
+                mapped.extend(std::iter::repeat_n(' ', alias.chars().count() - 1));
+                rest = &rest[alias.len()..];
+            }
+            None => {
+                mapped.push(symbol);
+                rest = &rest[symbol.len_utf8()..];
+            }
+        }
+    }
+    mapped
+}
+
+/// like `tokenize`, but every opcode comes with the position of its symbol.
+/// the optimizer keeps the position of the first symbol that went into an
+/// opcode, and `flatten` makes exactly one instruction of every optimized
+/// opcode but `IfEnd`, so without those the positions end up lining up with
+/// the flattened program
+pub fn tokenize_with_positions(source: &str) -> Vec<(OpCode, Position)> {
+    tokenize_with_comments(source).into_iter().filter_map(|lexeme| match lexeme {
+        Lexeme::Op(op, position) => Some((op, position)),
+        Lexeme::Comment(..) => None,
+    }).collect()
+}
+
+/// a piece of the source, as `tokenize_with_comments` splits it up
+#[derive(Clone, Debug, PartialEq)]
+pub enum Lexeme<'a> {
+    /// a symbol and where it is
+    Op(OpCode, Position),
+    /// everything between two symbols, newlines included, and where it starts
+    Comment(&'a str, Position),
+}
+
+/// splits the source into its symbols and the comments between them, so
+/// tools like formatters can put the source back together. every character
+/// ends up in exactly one lexeme, in order
+pub fn tokenize_with_comments(source: &str) -> Vec<Lexeme<'_>> {
+    let mut lexemes = Vec::new();
+    let mut position = Position { line: 1, column: 1 };
+    // where the comment being read started, in bytes and in lines and columns
+    let mut comment: Option<(usize, Position)> = None;
+    for (at, symbol) in source.char_indices() {
+        match symbol_opcode(symbol) {
+            Some(op) => {
+                if let Some((start, start_position)) = comment.take() {
+                    lexemes.push(Lexeme::Comment(&source[start..at], start_position));
+                }
+                lexemes.push(Lexeme::Op(op, position));
+            }
+            None => {
+                comment.get_or_insert((at, position));
+            }
+        }
+        if symbol == '\n' {
+            position = Position { line: position.line + 1, column: 1 };
+        } else {
+            position.column += 1;
+        }
+    }
+    if let Some((start, start_position)) = comment {
+        lexemes.push(Lexeme::Comment(&source[start..], start_position));
+    }
+    lexemes
+}
+
+fn symbol_opcode(symbol: char) -> Option<OpCode> {
+    match symbol {
+        '>' => Some(OpCode::IncrementPointer),
+        '<' => Some(OpCode::DecrementPointer),
+        '+' => Some(OpCode::Increment),
+        '-' => Some(OpCode::Decrement),
+        '.' => Some(OpCode::Write),
+        ',' => Some(OpCode::Read),
+        '[' => Some(OpCode::LoopBegin),
+        ']' => Some(OpCode::LoopEnd),
+        '|' => Some(OpCode::TapeState), // additional, mostly for debug
+        '#' => Some(OpCode::TapeWindow), // the debug command most other interpreters know
+        // the stack extension some dialects have, see `Instruction::Push`
+        #[cfg(feature = "stack")]
+        ':' => Some(OpCode::Push),
+        #[cfg(feature = "stack")]
+        ';' => Some(OpCode::Pop),
+        _ => None,
+    }
+}
+
+/// checks that every bracket in `source` has a partner. `parse` only sees
+/// opcodes, so this is where bracket errors can point at the right spot in the source
+pub fn check_brackets(source: &str) -> Result<(), ParseError> {
+    let mut open: Vec<Position> = Vec::new();
+    for (line, text) in source.lines().enumerate() {
+        for (column, symbol) in text.chars().enumerate() {
+            let at = Position { line: line + 1, column: column + 1 };
+            match symbol {
+                '[' => open.push(at),
+                ']' if open.pop().is_none() => return Err(ParseError::StrayClosingBracket(at)),
+                _ => (),
+            }
+        }
+    }
+    match open.first() {
+        Some(at) => Err(ParseError::StrayOpeningBracket(*at)),
+        None => Ok(()),
+    }
+}
+
+/// makes the brackets in `opcodes` match up for programs that are a bit
+/// broken: a `LoopEnd` without a `LoopBegin` is dropped, and every
+/// `LoopBegin` that's still open at the end is closed there. returns how
+/// many brackets it had to drop or add
+pub fn balance_brackets<T: Token>(opcodes: &mut Vec<T>) -> usize {
+    let mut open = Vec::new();
+    let mut dropped = 0;
+    opcodes.retain(|token| match token.op() {
+        OpCode::LoopBegin => {
+            open.push(token.clone());
+            true
+        }
+        OpCode::LoopEnd if open.pop().is_none() => {
+            dropped += 1;
+            false
+        }
+        _ => true,
+    });
+    let closed = open.len();
+    // the innermost loop closes first
+    opcodes.extend(open.iter().rev().map(|begin| begin.with_op(OpCode::LoopEnd)));
+    dropped + closed
+}
+
+/// what the optimizer passes work on: plain opcodes, or opcodes that remember
+/// where in the source they came from, see `tokenize_with_positions`
+pub trait Token: Clone + PartialEq {
+    fn op(&self) -> &OpCode;
+    /// a token for `op` that came from the same place as this one
+    fn with_op(&self, op: OpCode) -> Self;
+}
+
+impl Token for OpCode {
+    fn op(&self) -> &OpCode {
+        self
+    }
+
+    fn with_op(&self, op: OpCode) -> Self {
+        op
+    }
+}
+
+impl Token for (OpCode, Position) {
+    fn op(&self) -> &OpCode {
+        &self.0
+    }
+
+    fn with_op(&self, op: OpCode) -> Self {
+        (op, self.1)
+    }
+}
+
+/// an optimization pass, rewriting the opcodes in place. see `PASSES`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pass {
+    Reset,
+    DeadStores,
+    Scan,
+    Multiply,
+    RunLength,
+    Set,
+    Moves,
+    Offsets,
+    ClearRange,
+    KnownZero,
+    EmptyLoops,
+    If,
+}
+
+impl Pass {
+    pub fn run<T: Token>(self, opcodes: &mut Vec<T>) {
+        match self {
+            Pass::Reset => reset_cells(opcodes),
+            Pass::DeadStores => dead_stores(opcodes),
+            Pass::Scan => scan_cells(opcodes),
+            Pass::Multiply => multiply_loops(opcodes),
+            Pass::RunLength => run_length(opcodes),
+            Pass::Set => set_cells(opcodes),
+            Pass::Moves => coalesce_moves(opcodes),
+            Pass::Offsets => fold_offsets(opcodes),
+            Pass::ClearRange => clear_ranges(opcodes),
+            Pass::KnownZero => known_zero(opcodes),
+            Pass::EmptyLoops => empty_loops(opcodes),
+            Pass::If => if_loops(opcodes),
+        }
+    }
+}
+
+/// every optimization pass by name, in the order `optimize_opcodes` runs them
+pub const PASSES: &[(&str, Pass)] = &[
+    ("reset", Pass::Reset),
+    ("dead-stores", Pass::DeadStores),
+    ("scan", Pass::Scan),
+    ("multiply", Pass::Multiply),
+    ("run-length", Pass::RunLength),
+    ("set", Pass::Set),
+    ("moves", Pass::Moves),
+    ("offsets", Pass::Offsets),
+    ("clear-range", Pass::ClearRange),
+    ("known-zero", Pass::KnownZero),
+    ("empty-loops", Pass::EmptyLoops),
+    ("if", Pass::If),
+];
+
+/// the optimizer gives up after this many rounds, even if the last one still changed something
+const MAX_OPTIMIZE_ROUNDS: usize = 16;
+
+/// runs every pass in `PASSES`, see `optimize_with`. this happens on the
+/// flat opcodes before `parse` builds the tree, so loops at any depth are
+/// optimized the same way
+pub fn optimize_opcodes<T: Token>(opcodes: &mut Vec<T>) {
+    let passes: Vec<Pass> = PASSES.iter().map(|(_, pass)| *pass).collect();
+    optimize_with(opcodes, &passes);
+}
+
+/// runs `passes` in order, in rounds until a round doesn't change anything
+/// anymore, since every round can expose new patterns to the next one.
+pub fn optimize_with<T: Token>(opcodes: &mut Vec<T>, passes: &[Pass]) {
+    for _ in 0..MAX_OPTIMIZE_ROUNDS {
+        let before = opcodes.clone();
+        for pass in passes {
+            pass.run(opcodes);
+        }
+        if *opcodes == before {
+            break;
+        }
+    }
+}
+
+/// one change an optimizer pass made, see `optimize_logged`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rewrite<T> {
+    pub pass: Pass,
+    /// the round it happened in, counting from 1
+    pub round: usize,
+    /// where `removed` started in the opcodes the pass ran on
+    pub at: usize,
+    pub removed: Vec<T>,
+    pub inserted: Vec<T>,
+}
+
+/// a token that remembers which opcode it came from, so `optimize_logged`
+/// can tell afterwards what a pass did without the passes knowing about it
+#[derive(Clone, PartialEq)]
+struct Traced<T> {
+    token: T,
+    from: usize,
+}
+
+impl<T: Token> Token for Traced<T> {
+    fn op(&self) -> &OpCode {
+        self.token.op()
+    }
+
+    fn with_op(&self, op: OpCode) -> Self {
+        Traced { token: self.token.with_op(op), from: self.from }
+    }
+}
+
+/// does the same as `optimize_with`, and returns every change the passes
+/// made along the way, in the order they made them
+pub fn optimize_logged<T: Token>(opcodes: &mut Vec<T>, passes: &[Pass]) -> Vec<Rewrite<T>> {
+    let mut log = Vec::new();
+    for round in 1..=MAX_OPTIMIZE_ROUNDS {
+        let mut changed = false;
+        for pass in passes {
+            let mut traced: Vec<Traced<T>> = opcodes.iter().enumerate().map(|(from, token)| Traced { token: token.clone(), from }).collect();
+            pass.run(&mut traced);
+            let rewrites = rewrites(*pass, round, opcodes, &traced);
+            changed |= !rewrites.is_empty();
+            log.extend(rewrites);
+            *opcodes = traced.into_iter().map(|traced| traced.token).collect();
+        }
+        if !changed {
+            break;
+        }
+    }
+    log
+}
+
+/// lines up what a pass left behind with what it got. tokens it kept as
+/// they were are the fixed points, everything between two of them was
+/// rewritten. where the new tokens come from increasing places, every one
+/// of them is a rewrite of its own, like two `[-]` next to each other
+fn rewrites<T: Token>(pass: Pass, round: usize, before: &[T], after: &[Traced<T>]) -> Vec<Rewrite<T>> {
+    let mut log = Vec::new();
+    // the first token of `before` and `after` that isn't accounted for yet
+    let (mut i, mut j) = (0, 0);
+    for k in 0..=after.len() {
+        let kept = match after.get(k) {
+            // a kept token that something rewritten before it got moved past isn't a fixed point
+            Some(traced) if traced.from >= i && traced.token == before[traced.from] && after[j..k].iter().all(|new| new.from < traced.from) => traced.from,
+            Some(_) => continue,
+            None => before.len(),
+        };
+        let inserted = &after[j..k];
+        let starts: Vec<usize> = inserted.iter().map(|traced| traced.from).collect();
+        if !inserted.is_empty() && starts.windows(2).all(|pair| pair[0] < pair[1]) && starts[0] >= i && starts[starts.len() - 1] < kept {
+            for (n, traced) in inserted.iter().enumerate() {
+                let start = if n == 0 { i } else { traced.from };
+                let end = starts.get(n + 1).copied().unwrap_or(kept);
+                log.push(Rewrite { pass, round, at: start, removed: before[start..end].to_vec(), inserted: vec![traced.token.clone()] });
+            }
+        } else if i < kept || !inserted.is_empty() {
+            log.push(Rewrite { pass, round, at: i, removed: before[i..kept].to_vec(), inserted: inserted.iter().map(|traced| traced.token.clone()).collect() });
+        }
+        i = kept + 1;
+        j = k + 1;
+    }
+    log
+}
+
+/// the loop body if `opcodes[i]` starts a loop with exactly one opcode in it.
+/// a bracket isn't a body: the `[]]` at the end of `[-[]]` closes two loops
+fn single_op_loop<T: Token>(opcodes: &[T], i: usize) -> Option<&OpCode> {
+    match opcodes.get(i..i + 3) {
+        Some([begin, body, end]) if *begin.op() == OpCode::LoopBegin && matches!(end.op(), OpCode::LoopEnd | OpCode::IfEnd)
+            && !matches!(body.op(), OpCode::LoopBegin | OpCode::LoopEnd | OpCode::IfEnd) => Some(body.op()),
+        _ => None,
+    }
+}
+
+/// whether the current cell is always zero after `op`
+fn leaves_zero(op: &OpCode) -> bool {
+    matches!(op, OpCode::LoopEnd | OpCode::IfEnd | OpCode::ResetCell | OpCode::ScanCells(_) | OpCode::MultiplyMove(_) | OpCode::MoveCell(_) | OpCode::InfiniteLoop | OpCode::ClearRange(_))
+}
+
+/// whether `op` leaves the current cell at zero and does nothing at all
+/// when it starts on one. `ClearRange` isn't, it clears the cells after it
+/// and moves the pointer either way
+fn idle_on_zero(op: &OpCode) -> bool {
+    leaves_zero(op) && !matches!(op, OpCode::ClearRange(_))
+}
+
+/// `[-]` and `[+]` become `ResetCell`. a loop around a single opcode that
+/// always leaves the cell at zero, like the outer loop of `[[-]]`, can only
+/// run once and isn't needed either, as long as the opcode does nothing on a zero cell
+pub fn reset_cells<T: Token>(opcodes: &mut Vec<T>) {
+    let mut i = 0;
+    while i < opcodes.len() {
+        match single_op_loop(opcodes, i) {
+            Some(OpCode::Decrement | OpCode::Increment | OpCode::Sub(1) | OpCode::Add(1)) => {
+                let reset = opcodes[i].with_op(OpCode::ResetCell);
+                opcodes.drain(i..i + 3);
+                opcodes.insert(i, reset);
+            }
+            Some(op) if idle_on_zero(op) => {
+                opcodes.remove(i + 2);
+                opcodes.remove(i);
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+}
+
+/// drops changes to the current cell that a `ResetCell` or `SetCell` right
+/// after them throws away, like the `+++` in `+++[-]`, and resets of a cell
+/// that's already zero, like the second clear in `[-][-]` or one right after a loop
+pub fn dead_stores<T: Token>(opcodes: &mut Vec<T>) {
+    let mut i = 0;
+    while i < opcodes.len() {
+        if !matches!(opcodes[i].op(), OpCode::ResetCell | OpCode::SetCell(_)) || i == 0 {
+            i += 1;
+            continue;
+        }
+        match (opcodes[i - 1].op(), opcodes[i].op()) {
+            (OpCode::Increment | OpCode::Decrement | OpCode::Add(_) | OpCode::Sub(_) | OpCode::SetCell(_), _)
+            | (OpCode::ResetCell, OpCode::SetCell(_)) => {
+                // the store is at `i - 1` now and might make the op before it dead too
+                opcodes.remove(i - 1);
+                i -= 1;
+            }
+            (op, OpCode::ResetCell) if leaves_zero(op) => {
+                opcodes.remove(i);
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+/// `[>]` and `[<]` become `ScanCells`
+pub fn scan_cells<T: Token>(opcodes: &mut Vec<T>) {
+    let mut i = 0;
+    while i < opcodes.len() {
+        if let Some(body @ (OpCode::DecrementPointer | OpCode::IncrementPointer | OpCode::Move(-1) | OpCode::Move(1))) = single_op_loop(opcodes, i) {
+            // the direction has to be read before the loop is drained
+            let up = matches!(body, OpCode::IncrementPointer | OpCode::Move(1));
+            let scan = opcodes[i].with_op(OpCode::ScanCells(up));
+            opcodes.drain(i..i + 3);
+            opcodes.insert(i, scan);
+        }
+        i += 1;
+    }
+}
+
+/// empty loops `[]` become `InfiniteLoop`, so running into one on a nonzero
+/// cell is reported instead of hanging. they're usually a mistake, or what's
+/// left of a loop whose body cancelled out, like `[<>]`
+pub fn empty_loops<T: Token>(opcodes: &mut Vec<T>) {
+    let mut i = 0;
+    while i + 1 < opcodes.len() {
+        if *opcodes[i].op() == OpCode::LoopBegin && *opcodes[i + 1].op() == OpCode::LoopEnd {
+            let infinite = opcodes[i].with_op(OpCode::InfiniteLoop);
+            opcodes.drain(i..i + 2);
+            opcodes.insert(i, infinite);
+        }
+        i += 1;
+    }
+}
+
+/// loops whose body ends in something that leaves the current cell at zero,
+/// like `[>+<[-]]`, can only run once, so they end in `IfEnd` instead and
+/// `parse` makes an `Instruction::If` of them. it's the cell the pointer is
+/// on at the end that counts, whichever one that is
+pub fn if_loops<T: Token>(opcodes: &mut [T]) {
+    for i in 1..opcodes.len() {
+        if *opcodes[i].op() == OpCode::LoopEnd && leaves_zero(opcodes[i - 1].op()) {
+            opcodes[i] = opcodes[i].with_op(OpCode::IfEnd);
+        }
+    }
+}
+
+/// runs of `ResetCell` and `Move(1)` that clear the cells next to each
+/// other from the current one on, like `[-]>[-]>[-]`, become a `ClearRange`
+pub fn clear_ranges<T: Token>(opcodes: &mut Vec<T>) {
+    let cleared = |token: Option<&T>| match token.map(T::op) {
+        Some(OpCode::ResetCell) => Some(1),
+        Some(OpCode::ClearRange(len)) => Some(*len),
+        _ => None,
+    };
+    let mut i = 0;
+    while i < opcodes.len() {
+        let mut len = 0;
+        let mut j = i;
+        while let Some(cells) = cleared(opcodes.get(j)) {
+            len += cells;
+            j += 1;
+            if opcodes.get(j).map(T::op) != Some(&OpCode::Move(1)) || cleared(opcodes.get(j + 1)).is_none() {
+                break;
+            }
+            j += 1;
+        }
+        if j - i > 1 {
+            let range = opcodes[i].with_op(OpCode::ClearRange(len));
+            opcodes.drain(i..j);
+            opcodes.insert(i, range);
+        }
+        i += 1;
+    }
+}
+
+/// an `Add` or `Sub` on a cell that's known to be zero becomes a `SetCell`.
+/// a cell is known to be zero at the start of the program, since the tape
+/// starts out that way, and after anything that leaves it at zero, like a
+/// loop or a `ResetCell`, until the pointer moves or the cell is written.
+/// what's left of `+++[>+<-]>++` then is `SetCell(3) MoveCell(1) Move(1) Add(2)`.
+/// see `ZEROED_TAPE_ONLY`
+pub fn known_zero<T: Token>(opcodes: &mut [T]) {
+    let mut zero = true;
+    for token in opcodes.iter_mut() {
+        let set = match *token.op() {
+            OpCode::Increment if zero => Some(1),
+            OpCode::Decrement if zero => Some(u32::MAX),
+            OpCode::Add(count) if zero => Some(count),
+            OpCode::Sub(count) if zero => Some(count.wrapping_neg()),
+            _ => None,
+        };
+        if let Some(value) = set {
+            *token = token.with_op(OpCode::SetCell(value));
+        }
+        zero = match token.op() {
+            op if leaves_zero(op) => true,
+            OpCode::Write | OpCode::Push | OpCode::TapeState | OpCode::TapeWindow => zero,
+            // an `AddAt` can wrap all the way around the tape onto the current cell
+            _ => false,
+        };
+    }
+}
+
+/// multiply/copy loops like `[->+>++<<]` become `MultiplyMove`, or
+/// `MoveCell` if they only copy to a single cell
+pub fn multiply_loops<T: Token>(opcodes: &mut Vec<T>) {
+    let mut i = 0;
+    while i < opcodes.len() {
+        if *opcodes[i].op() == OpCode::LoopBegin {
+            if let Some((len, targets)) = multiply_loop(&opcodes[i..]) {
+                let multiply = opcodes[i].with_op(match targets[..] {
+                    [(offset, 1)] => OpCode::MoveCell(offset),
+                    _ => OpCode::MultiplyMove(targets),
+                });
+                opcodes.drain(i..i + len);
+                opcodes.insert(i, multiply);
+            }
+        }
+        i += 1;
+    }
+}
+
+/// runs of `+` and `-` become `Add` and `Sub`, and whatever `Add`s and
+/// `Sub`s end up next to each other are merged into one
+pub fn run_length<T: Token>(opcodes: &mut Vec<T>) {
+    let mut i = 0;
+    while i < opcodes.len() {
+        if !matches!(opcodes[i].op(), OpCode::Increment | OpCode::Decrement | OpCode::Add(_) | OpCode::Sub(_)) {
+            i += 1;
+            continue;
+        }
+
+        let mut net: u32 = 0;
+        let mut j = i;
+        while let Some(token) = opcodes.get(j) {
+            match token.op() {
+                OpCode::Increment => net = net.wrapping_add(1),
+                OpCode::Decrement => net = net.wrapping_sub(1),
+                OpCode::Add(count) => net = net.wrapping_add(*count),
+                OpCode::Sub(count) => net = net.wrapping_sub(*count),
+                _ => break,
+            }
+            j += 1;
+        }
+        let replacement = match net {
+            0 => None,
+            net if net > u32::MAX / 2 => Some(opcodes[i].with_op(OpCode::Sub(net.wrapping_neg()))),
+            net => Some(opcodes[i].with_op(OpCode::Add(net))),
+        };
+
+        opcodes.drain(i..j);
+        if let Some(replacement) = replacement {
+            opcodes.insert(i, replacement);
+            i += 1;
+        }
+    }
+}
+
+/// a `ResetCell` followed by `Add`s or `Sub`s, like `[-]+++`, becomes a
+/// `SetCell`, and so does a `SetCell` that gets added to
+pub fn set_cells<T: Token>(opcodes: &mut Vec<T>) {
+    let mut i = 0;
+    while i + 1 < opcodes.len() {
+        let value = match *opcodes[i].op() {
+            OpCode::ResetCell => 0,
+            OpCode::SetCell(value) => value,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let value = match *opcodes[i + 1].op() {
+            OpCode::Increment => value.wrapping_add(1),
+            OpCode::Decrement => value.wrapping_sub(1),
+            OpCode::Add(count) => value.wrapping_add(count),
+            OpCode::Sub(count) => value.wrapping_sub(count),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let set = opcodes[i].with_op(if value == 0 { OpCode::ResetCell } else { OpCode::SetCell(value) });
+        opcodes.drain(i..i + 2);
+        // stay at `i`, there might be more to add
+        opcodes.insert(i, set);
+    }
+}
+
+/// runs of `>` and `<` become a single `Move`, or nothing if they cancel out
+pub fn coalesce_moves<T: Token>(opcodes: &mut Vec<T>) {
+    let mut i = 0;
+    while i < opcodes.len() {
+        if !matches!(opcodes[i].op(), OpCode::IncrementPointer | OpCode::DecrementPointer | OpCode::Move(_)) {
+            i += 1;
+            continue;
+        }
+
+        let mut offset = 0;
+        let mut j = i;
+        while let Some(token) = opcodes.get(j) {
+            match token.op() {
+                OpCode::IncrementPointer => offset += 1,
+                OpCode::DecrementPointer => offset -= 1,
+                OpCode::Move(by) => offset += by,
+                _ => break,
+            }
+            j += 1;
+        }
+
+        let moved = opcodes[i].with_op(OpCode::Move(offset));
+        opcodes.drain(i..j);
+        if offset != 0 {
+            opcodes.insert(i, moved);
+            i += 1;
+        }
+    }
+}
+
+/// rewrites straight-line runs of `Move`/`Add`/`Sub`/`AddAt` into `AddAt`s
+/// relative to where the run started, followed by a single `Move` for the net
+/// motion. e.g. `Move(3) Add(5) Move(-3)` becomes just `AddAt(3, 5)`, and
+/// `AddAt(1, 1) Move(1) AddAt(1, 1) Move(1)` becomes `AddAt(1, 1) AddAt(2, 1) Move(2)`.
+pub fn fold_offsets<T: Token>(opcodes: &mut Vec<T>) {
+    let mut folded = Vec::with_capacity(opcodes.len());
+    let mut i = 0;
+    while i < opcodes.len() {
+        let mut j = i;
+        let mut first_move = None;
+        while j < opcodes.len() && matches!(opcodes[j].op(), OpCode::Move(_) | OpCode::Add(_) | OpCode::Sub(_) | OpCode::AddAt(_, _)) {
+            if let OpCode::Move(_) = opcodes[j].op() {
+                first_move = first_move.or(Some(j));
+            }
+            j += 1;
+        }
+
+        // a run without moves has nothing to fold
+        let Some(first_move) = first_move else {
+            folded.push(opcodes[i].clone());
+            i += 1;
+            continue;
+        };
+
+        let mut offset = 0;
+        for token in &opcodes[i..j] {
+            match *token.op() {
+                OpCode::Move(by) => offset += by,
+                OpCode::Add(count) if offset == 0 => folded.push(token.with_op(OpCode::Add(count))),
+                OpCode::Sub(count) if offset == 0 => folded.push(token.with_op(OpCode::Sub(count))),
+                OpCode::Add(count) => folded.push(token.with_op(OpCode::AddAt(offset, count))),
+                OpCode::Sub(count) => folded.push(token.with_op(OpCode::AddAt(offset, count.wrapping_neg()))),
+                OpCode::AddAt(at, count) if offset + at == 0 => folded.push(token.with_op(OpCode::Add(count))),
+                OpCode::AddAt(at, count) => folded.push(token.with_op(OpCode::AddAt(offset + at, count))),
+                _ => unreachable!(),
+            }
+        }
+        if offset != 0 {
+            folded.push(opcodes[first_move].with_op(OpCode::Move(offset)));
+        }
+        i = j;
+    }
+    *opcodes = folded;
+}
+
+/// checks if the loop starting at `opcodes[0]` is a multiply/copy loop like
+/// `[->+>++<<]`: only `+-<>` (or what other passes made of them) in the body,
+/// no net pointer motion, and the current cell decremented exactly once per iteration.
+/// returns the length of the loop in opcodes and the (offset, multiplier) pairs.
+fn multiply_loop<T: Token>(opcodes: &[T]) -> Option<(usize, Vec<(i32, u32)>)> {
+    let mut offset = 0;
+    let mut deltas: Vec<(i32, u32)> = Vec::new();
+    for (j, token) in opcodes.iter().enumerate().skip(1) {
+        let (at, delta) = match token.op() {
+            OpCode::IncrementPointer => { offset += 1; continue; }
+            OpCode::DecrementPointer => { offset -= 1; continue; }
+            OpCode::Move(by) => { offset += by; continue; }
+            OpCode::Increment => (offset, 1u32),
+            OpCode::Decrement => (offset, u32::MAX),
+            OpCode::Add(count) => (offset, *count),
+            OpCode::Sub(count) => (offset, count.wrapping_neg()),
+            OpCode::AddAt(by, count) => (offset + by, *count),
+            OpCode::LoopEnd => {
+                let counter = deltas.iter().find(|(off, _)| *off == 0).map(|(_, d)| *d);
+                if offset != 0 || counter != Some(u32::MAX) {
+                    return None;
+                }
+                deltas.retain(|(off, mul)| *off != 0 && *mul != 0);
+                return Some((j + 1, deltas));
+            }
+            _ => return None,
+        };
+        match deltas.iter_mut().find(|(off, _)| *off == at) {
+            Some((_, d)) => *d = d.wrapping_add(delta),
+            None => deltas.push((at, delta)),
+        }
+    }
+    None
+}
+
+pub fn parse(opcodes: Vec<OpCode>) -> Result<Vec<Instruction>, ParseError> {
+    let mut program: Vec<Instruction> = Vec::new();
+    // for every loop we're inside of: where it started, and the
+    // instructions of the enclosing body we'll return to once it ends
+    let mut loop_stack: Vec<(usize, Vec<Instruction>)> = Vec::new();
+
+    for (i, op) in opcodes.into_iter().enumerate() {
+        let instr = match op {
+
+            OpCode::Move(offset)  => Some(Instruction::Move(offset)),
+            OpCode::Add(count) => Some(Instruction::Add(count)),
+            OpCode::Sub(count) => Some(Instruction::Sub(count)),
+            OpCode::Write               => Some(Instruction::Write),
+            OpCode::Read                => Some(Instruction::Read),
+            OpCode::ResetCell           => Some(Instruction::ResetCell),
+            OpCode::SetCell(value)      => Some(Instruction::SetCell(value)),
+            OpCode::ScanCells(bool) => Some(Instruction::ScanCells(bool)),
+            OpCode::MultiplyMove(targets) => Some(Instruction::MultiplyMove(targets)),
+            OpCode::MoveCell(offset) => Some(Instruction::MoveCell(offset)),
+            OpCode::AddAt(offset, count) => Some(Instruction::AddAt(offset, count)),
+
+            OpCode::LoopBegin => {
+                loop_stack.push((i, std::mem::take(&mut program)));
+                None
+            },
+
+            OpCode::LoopEnd => match loop_stack.pop() {
+                Some((_, outer)) => Some(Instruction::Loop(std::mem::replace(&mut program, outer))),
+                None => return Err(ParseError::StrayClosingBracket(opcode_position(i))),
+            },
+            OpCode::IfEnd => match loop_stack.pop() {
+                Some((_, outer)) => Some(Instruction::If(std::mem::replace(&mut program, outer))),
+                None => return Err(ParseError::StrayClosingBracket(opcode_position(i))),
+            },
+
+            // only left over if the optimizer didn't run
+            OpCode::IncrementPointer    => Some(Instruction::Move(1)),
+            OpCode::DecrementPointer    => Some(Instruction::Move(-1)),
+            OpCode::Increment           => Some(Instruction::Add(1)),
+            OpCode::Decrement           => Some(Instruction::Sub(1)),
+            OpCode::TapeState           => Some(Instruction::TapeState),
+            OpCode::TapeWindow          => Some(Instruction::TapeWindow),
+            OpCode::InfiniteLoop        => Some(Instruction::InfiniteLoop),
+            OpCode::ClearRange(len)     => Some(Instruction::ClearRange(len)),
+            OpCode::Push                => Some(Instruction::Push),
+            OpCode::Pop                 => Some(Instruction::Pop),
+        };
+
+        if let Some(instr) = instr {
+            program.push(instr);
+        }
+    }
+
+    if let Some((loop_start, _)) = loop_stack.first() {
+        return Err(ParseError::StrayOpeningBracket(opcode_position(*loop_start)));
+    }
+
+    Ok(program)
+}
+
+/// opcodes don't know where they came from, so `parse` treats them as one
+/// line with an opcode in every column. run `check_brackets` on the source
+/// first to get real positions
+fn opcode_position(index: usize) -> Position {
+    Position { line: 1, column: index + 1 }
+}
+
+/// pretty-prints the instruction tree, one instruction per line and
+/// indented by two spaces for every loop it's nested in
+pub fn write_ir<W: Write>(program: &[Instruction], out: &mut W) -> std::io::Result<()> {
+    write_ir_at(program, 0, out)
+}
+
+fn write_ir_at<W: Write>(program: &[Instruction], depth: usize, out: &mut W) -> std::io::Result<()> {
+    let indent = "  ".repeat(depth);
+    for instr in program {
+        match instr {
+            Instruction::Loop(body) | Instruction::If(body) => {
+                writeln!(out, "{}{} {{", indent, if matches!(instr, Instruction::If(_)) { "If" } else { "Loop" })?;
+                write_ir_at(body, depth + 1, out)?;
+                writeln!(out, "{}}}", indent)?;
+            }
+            _ => writeln!(out, "{}{:?}", indent, instr)?,
+        }
+    }
+    Ok(())
+}
+
+/// writes `program` as a numbered listing like `0001 JumpIfZero 0005`, with
+/// jump targets as the index they go to and added values that wrapped around
+/// as negative numbers. with a `source_map`, every line ends with where its
+/// instruction came from.
+pub fn write_disassembly<W: Write>(program: &[FlatInstr], source_map: &[Position], out: &mut W) -> std::io::Result<()> {
+    let signed = |value: u32| value as i32;
+    let lines: Vec<String> = program.iter().map(|instr| {
+        let operands = match instr {
+            FlatInstr::Move(offset) | FlatInstr::MoveCell(offset) => format!("{:+}", offset),
+            FlatInstr::Add(value) | FlatInstr::Sub(value) | FlatInstr::SetCell(value) | FlatInstr::ClearRange(value) => value.to_string(),
+            FlatInstr::ScanCells(right) => (if *right { "right" } else { "left" }).to_string(),
+            FlatInstr::AddAt(offset, value) => format!("{:+} {}", offset, signed(*value)),
+            FlatInstr::MultiplyMove(targets) => targets.iter().map(|(offset, factor)| format!("{:+}*{}", offset, signed(*factor))).collect::<Vec<_>>().join(" "),
+            FlatInstr::JumpIfZero(target) | FlatInstr::JumpIfNonZero(target) => format!("{:04}", target),
+            _ => String::new(),
+        };
+        format!("{} {}", instr.name(), operands).trim_end().to_string()
+    }).collect();
+    // the positions line up after the longest instruction
+    let width = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+    for (i, line) in lines.iter().enumerate() {
+        match source_map.get(i) {
+            Some(position) => writeln!(out, "{:04} {:<width$}  ; {}", i, line, position, width = width)?,
+            None => writeln!(out, "{:04} {}", i, line)?,
+        }
+    }
+    Ok(())
+}
+
+/// whether any instruction in `program`, or in the body of one of its loops,
+/// is one that `matches` says yes to, e.g. to find programs that never write
+pub fn any_instruction(program: &[Instruction], matches: &impl Fn(&Instruction) -> bool) -> bool {
+    program.iter().any(|instr| match instr {
+        Instruction::Loop(body) | Instruction::If(body) => matches(instr) || any_instruction(body, matches),
+        _ => matches(instr),
+    })
+}
+
+/// looks for a cell outside of `0..tape_size` that the program is sure to
+/// reach when it starts at cell 0, and returns the first one it finds.
+/// the data pointer can only be followed until a scan or a loop that moves
+/// it, so this only catches mistakes like a `Move(40000)` on a 30000 cell
+/// tape. loop bodies are checked as if they run once.
+pub fn find_out_of_bounds(program: &[Instruction], tape_size: usize) -> Option<i64> {
+    let mut position = Some(0);
+    out_of_bounds_from(program, &mut position, tape_size as i64)
+}
+
+/// checks `program` starting at `position`, and leaves `position` where the
+/// data pointer ends up, or at `None` once it can't be known anymore
+fn out_of_bounds_from(program: &[Instruction], position: &mut Option<i64>, tape_size: i64) -> Option<i64> {
+    let outside = |cell: i64| !(0..tape_size).contains(&cell);
+    for instr in program {
+        let at = (*position)?;
+        let reached: Vec<i64> = match instr {
+            Instruction::Move(offset) => {
+                *position = Some(at + *offset as i64);
+                vec![at + *offset as i64]
+            }
+            Instruction::AddAt(offset, _) | Instruction::MoveCell(offset) => vec![at + *offset as i64],
+            Instruction::ClearRange(len) => {
+                *position = Some(at + *len as i64 - 1);
+                vec![at + *len as i64 - 1]
+            }
+            Instruction::MultiplyMove(targets) => targets.iter().map(|(offset, _)| at + *offset as i64).collect(),
+            Instruction::ScanCells(_) => {
+                *position = None;
+                Vec::new()
+            }
+            Instruction::Loop(body) | Instruction::If(body) => {
+                let mut inside = Some(at);
+                if let Some(cell) = out_of_bounds_from(body, &mut inside, tape_size) {
+                    return Some(cell);
+                }
+                // a loop that moves the pointer could end up anywhere
+                if inside != Some(at) {
+                    *position = None;
+                }
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+        if let Some(cell) = reached.into_iter().find(|cell| outside(*cell)) {
+            return Some(cell);
+        }
+    }
+    None
+}
+
+/// turns the parsed instruction tree into a flat list that `execute` can
+/// walk with a single instruction pointer. every `Loop` becomes a
+/// `JumpIfZero` and `JumpIfNonZero` pair pointing just past each other.
+/// an `If` only gets the `JumpIfZero`, which jumps past its body.
+pub fn flatten(program: &[Instruction]) -> Vec<FlatInstr> {
+    let mut flat = Vec::new();
+    // the instructions left to visit at each nesting level, the index of
+    // the `JumpIfZero` that opened it, and whether it's a loop or an `If`
+    let mut stack = vec![(program.iter(), None::<(usize, bool)>)];
+
+    while let Some((body, begin)) = stack.last_mut() {
+        let begin = *begin;
+        let instr = match body.next() {
+            Some(instr @ (Instruction::Loop(nested) | Instruction::If(nested))) => {
+                // the target gets patched in once the end of the loop is known
+                flat.push(FlatInstr::JumpIfZero(0));
+                stack.push((nested.iter(), Some((flat.len() - 1, matches!(instr, Instruction::Loop(_))))));
+                continue;
+            }
+            Some(Instruction::Move(offset)) => FlatInstr::Move(*offset),
+            Some(Instruction::Add(count)) => FlatInstr::Add(*count),
+            Some(Instruction::Sub(count)) => FlatInstr::Sub(*count),
+            Some(Instruction::AddAt(offset, count)) => FlatInstr::AddAt(*offset, *count),
+            Some(Instruction::Write) => FlatInstr::Write,
+            Some(Instruction::Read) => FlatInstr::Read,
+            Some(Instruction::ResetCell) => FlatInstr::ResetCell,
+            Some(Instruction::SetCell(value)) => FlatInstr::SetCell(*value),
+            Some(Instruction::ScanCells(direction)) => FlatInstr::ScanCells(*direction),
+            Some(Instruction::MultiplyMove(targets)) => FlatInstr::MultiplyMove(targets.clone()),
+            Some(Instruction::MoveCell(offset)) => FlatInstr::MoveCell(*offset),
+            Some(Instruction::TapeState) => FlatInstr::TapeState,
+            Some(Instruction::TapeWindow) => FlatInstr::TapeWindow,
+            Some(Instruction::InfiniteLoop) => FlatInstr::InfiniteLoop,
+            Some(Instruction::ClearRange(len)) => FlatInstr::ClearRange(*len),
+            Some(Instruction::Push) => FlatInstr::Push,
+            Some(Instruction::Pop) => FlatInstr::Pop,
+            None => {
+                stack.pop();
+                match begin {
+                    Some((begin, true)) => {
+                        flat[begin] = FlatInstr::JumpIfZero(flat.len() + 1);
+                        flat.push(FlatInstr::JumpIfNonZero(begin + 1));
+                    }
+                    Some((begin, false)) => flat[begin] = FlatInstr::JumpIfZero(flat.len()),
+                    None => (),
+                }
+                continue;
+            }
+        };
+        flat.push(instr);
+    }
+
+    flat
+}
+
+/// the cell `offset` cells away from `data_pointer`, wrapping around the tape
+/// or stopping at its ends. with `TapeMode::Error` it fails with the index
+/// it would have landed on instead, and `step` fills in the instruction.
+fn offset_index(data_pointer: usize, offset: i32, len: usize, mode: TapeMode) -> Result<usize, RuntimeError> {
+    let target = data_pointer as i64 + offset as i64;
+    match mode {
+        // taking it as a usize first would wrap around all of usize, not the tape
+        TapeMode::Wrap => Ok(target.rem_euclid(len as i64) as usize),
+        TapeMode::Clamp => Ok(target.clamp(0, len as i64 - 1) as usize),
+        TapeMode::Error if (0..len as i64).contains(&target) => Ok(target as usize),
+        TapeMode::Error => Err(RuntimeError::PointerOutOfBounds(target, 0)),
+    }
+}
+
+/// `cell` plus `count`, held at 0 and `C::MAX` instead of wrapping. like the
+/// counts `fold_offsets` makes for `AddAt`, `count` is taken as negative
+/// if it's past `i32::MAX`
+fn saturating_add<C: Cell>(cell: C, count: u32) -> C {
+    let sum = cell.to_u32() as i64 + count as i32 as i64;
+    C::from_u32(sum.clamp(0, C::MAX.to_u32() as i64) as u32)
+}
+
+/// finds the nearest zero cell from `data_pointer` onwards, to the right if
+/// `right` is set and to the left otherwise. like `Move`, the scan wraps
+/// around the ends of the tape with `TapeMode::Wrap`. with `TapeMode::Error`
+/// running off the end is an error, and with `TapeMode::Clamp` the loop would
+/// get stuck on the last cell.
+fn scan_for_zero<C: Cell>(tape: &[C], data_pointer: usize, right: bool, mode: TapeMode) -> Result<usize, RuntimeError> {
+    let nearest = if right {
+        tape[data_pointer..].iter().position(|x| x.is_zero()).map(|i| data_pointer + i)
+    } else {
+        tape[..=data_pointer].iter().rposition(|x| x.is_zero())
+    };
+    if let Some(index) = nearest {
+        return Ok(index);
+    }
+    match mode {
+        TapeMode::Wrap => (),
+        TapeMode::Clamp => return Err(RuntimeError::NoZeroCell),
+        TapeMode::Error => return Err(RuntimeError::PointerOutOfBounds(if right { tape.len() as i64 } else { -1 }, 0)),
+    }
+
+    let wrapped = if right {
+        tape[..data_pointer].iter().position(|x| x.is_zero())
+    } else {
+        tape[data_pointer + 1..].iter().rposition(|x| x.is_zero()).map(|i| data_pointer + 1 + i)
+    };
+    wrapped.ok_or(RuntimeError::NoZeroCell)
+}
+
+/// how many instructions `execute` runs between checks of `Options::timeout`
+/// and `Options::interrupt`
+const TIMEOUT_CHECK_INTERVAL: u64 = 1 << 16;
+
+/// whether the run has to stop before the instruction at `instr_pointer`,
+/// with `steps` instructions run so far and `deadline` from `options.timeout`
+#[inline]
+pub(crate) fn check_limits(options: &Options, steps: u64, instr_pointer: usize, deadline: Option<std::time::Instant>) -> Result<(), RuntimeError> {
+    if options.max_steps.is_some_and(|max_steps| steps >= max_steps) {
+        return Err(RuntimeError::StepLimitReached(steps, instr_pointer));
+    }
+    // looking at the clock is slow, so only do it every now and then
+    if steps.is_multiple_of(TIMEOUT_CHECK_INTERVAL) {
+        if deadline.is_some_and(|deadline| std::time::Instant::now() >= deadline) {
+            return Err(RuntimeError::TimedOut(options.timeout.unwrap()));
+        }
+        if options.interrupt.is_some_and(|interrupt| interrupt.load(std::sync::atomic::Ordering::Relaxed)) {
+            return Err(RuntimeError::Interrupted(steps, instr_pointer));
+        }
+    }
+    Ok(())
+}
+
+/// executes a program that was previously parsed and flattened.
+/// `Read` takes bytes from `input` and output is written to `output`, which
+/// is flushed before every read so prompts show up before the program blocks on input,
+/// unless `options.buffer_across_reads` is set.
+///
+/// `TapeState` prints the values of the cells up to the last nonzero one,
+/// like `[72 >101 108]`, with a `>` in front of the cell under the data pointer.
+///
+/// `Write` outputs 8 bit cells as the raw byte, so programs can write binary
+/// data. wider cells are written as a unicode codepoint (utf-8 encoded) so they
+/// can print any character, cells that aren't a valid codepoint print as U+FFFD.
+/// `Read` stores one byte, or with `options.utf8_input` and wider cells a
+/// whole character.
+///
+/// `options.tape_mode` decides what happens wherever the data pointer comes
+/// to rest or is used to reach a cell off the tape. by default it wraps
+/// around, and a `ScanCells` on a tape with no zero cell at all stops with
+/// `RuntimeError::NoZeroCell` instead of hanging.
+/// if `stats` is given, every executed instruction is recorded in it.
+/// `options.max_steps` counts optimized instructions, not brainfuck symbols.
+pub fn execute<C: Cell, R: Read, W: Write>(program: &[FlatInstr], tape: &mut [C], data_pointer: &mut usize, input: &mut R, output: &mut W, options: &Options, mut stats: Option<&mut Stats>) -> Result<(), RuntimeError> {
+    let mut instr_pointer = 0;
+    let mut stack = Vec::new();
+    let mut steps = 0;
+    let deadline = options.timeout.map(|timeout| std::time::Instant::now() + timeout);
+    while let Some(instr) = program.get(instr_pointer) {
+        check_limits(options, steps, instr_pointer, deadline)?;
+        steps += 1;
+        if let Some(stats) = stats.as_deref_mut() {
+            stats.record(instr_pointer, instr);
+        }
+        let before = *data_pointer;
+        step(program, &mut instr_pointer, tape, data_pointer, &mut stack, input, output, options)?;
+        if let Some(stats) = stats.as_deref_mut() {
+            stats.record_cells(instr, before, *data_pointer, tape.len(), options.tape_mode);
+        }
+    }
+
+    Ok(())
+}
+
+/// like `execute`, but calls `hook` before every instruction with its index,
+/// the instruction itself, the tape and the data pointer, e.g. to trace or
+/// animate a program. returning `ControlFlow::Break` from it stops the
+/// program before that instruction runs, and `execute_with_hook` returns `Ok`
+pub fn execute_with_hook<C: Cell, R: Read, W: Write, F: FnMut(usize, &FlatInstr, &[C], usize) -> ControlFlow<()>>(program: &[FlatInstr], tape: &mut [C], data_pointer: &mut usize, input: &mut R, output: &mut W, options: &Options, mut hook: F) -> Result<(), RuntimeError> {
+    let mut instr_pointer = 0;
+    let mut stack = Vec::new();
+    let mut steps = 0;
+    let deadline = options.timeout.map(|timeout| std::time::Instant::now() + timeout);
+    while let Some(instr) = program.get(instr_pointer) {
+        check_limits(options, steps, instr_pointer, deadline)?;
+        steps += 1;
+        if hook(instr_pointer, instr, tape, *data_pointer).is_break() {
+            break;
+        }
+        step(program, &mut instr_pointer, tape, data_pointer, &mut stack, input, output, options)?;
+    }
+
+    Ok(())
+}
+
+/// like `execute`, but instead of wrapping around, the tape grows whenever
+/// the data pointer goes past its end, so it can start out small. the new
+/// cells are zero. moving left of the first cell stops with
+/// `RuntimeError::PointerOutOfBounds`, and `options.tape_mode` is ignored.
+pub fn execute_growing<C: Cell, R: Read, W: Write>(program: &[FlatInstr], tape: &mut Vec<C>, data_pointer: &mut usize, input: &mut R, output: &mut W, options: &Options, mut stats: Option<&mut Stats>) -> Result<(), RuntimeError> {
+    let options = Options { tape_mode: TapeMode::Error, ..options.clone() };
+    let mut instr_pointer = 0;
+    let mut stack = Vec::new();
+    let mut steps = 0;
+    let deadline = options.timeout.map(|timeout| std::time::Instant::now() + timeout);
+    while let Some(instr) = program.get(instr_pointer) {
+        check_limits(&options, steps, instr_pointer, deadline)?;
+        let at = instr_pointer;
+        let before = *data_pointer;
+        match step(program, &mut instr_pointer, tape, data_pointer, &mut stack, input, output, &options) {
+            // instructions check where they go before changing anything,
+            // so this one can simply run again on the longer tape
+            Err(RuntimeError::PointerOutOfBounds(target, _)) if target >= 0 => {
+                instr_pointer -= 1;
+                let len = (target as usize + 1).max(tape.len() * 2);
+                tape.resize(len, C::default());
+            }
+            result => {
+                result?;
+                steps += 1;
+                if let Some(stats) = stats.as_deref_mut() {
+                    stats.record(at, instr);
+                    stats.record_cells(instr, before, *data_pointer, tape.len(), options.tape_mode);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// executes the single instruction at `instr_pointer`, the same way `execute`
+/// would, and moves `instr_pointer` on to the instruction that runs next.
+/// returns `Ok(false)` without doing anything once the program has ended.
+/// `stack` is what `Push` and `Pop` work on, and starts out empty.
+#[inline]
+#[allow(clippy::too_many_arguments)]
+pub fn step<C: Cell, R: Read, W: Write>(program: &[FlatInstr], instr_pointer: &mut usize, tape: &mut [C], data_pointer: &mut usize, stack: &mut Vec<C>, input: &mut R, output: &mut W, options: &Options) -> Result<bool, RuntimeError> {
+    let Some(instr) = program.get(*instr_pointer) else {
+        return Ok(false);
+    };
+    let at = *instr_pointer;
+    *instr_pointer += 1;
+    run_instr(instr, instr_pointer, tape, data_pointer, stack, input, output, options).map_err(|e| match e {
+        // the helpers that find the cell don't know which instruction they ran for
+        RuntimeError::PointerOutOfBounds(cell, _) => RuntimeError::PointerOutOfBounds(cell, at),
+        e => e,
+    })?;
+
+    Ok(true)
+}
+
+/// the body of `step`, with `instr_pointer` already past `instr`
+#[inline(always)]
+#[allow(clippy::too_many_arguments)]
+fn run_instr<C: Cell, R: Read, W: Write>(instr: &FlatInstr, instr_pointer: &mut usize, tape: &mut [C], data_pointer: &mut usize, stack: &mut Vec<C>, input: &mut R, output: &mut W, options: &Options) -> Result<(), RuntimeError> {
+    let mode = options.tape_mode;
+    let saturate = options.cell_overflow == CellOverflow::Saturate;
+    match instr {
+        FlatInstr::Move(offset) => *data_pointer = offset_index(*data_pointer, *offset, tape.len(), mode)?,
+        FlatInstr::Add(count) if saturate => tape[*data_pointer] = saturating_add(tape[*data_pointer], *count),
+        FlatInstr::Sub(count) if saturate => tape[*data_pointer] = saturating_add(tape[*data_pointer], count.wrapping_neg()),
+        FlatInstr::Add(count) => tape[*data_pointer] = 
+            tape[*data_pointer].wrapping_add(C::from_u32(*count)),
+        FlatInstr::Sub(count) => tape[*data_pointer] = 
+            tape[*data_pointer].wrapping_sub(C::from_u32(*count)),
+        FlatInstr::AddAt(offset, count) => {
+            let target = offset_index(*data_pointer, *offset, tape.len(), mode)?;
+            tape[target] = if saturate {
+                saturating_add(tape[target], *count)
+            } else {
+                tape[target].wrapping_add(C::from_u32(*count))
+            };
+        }
+        FlatInstr::ResetCell => tape[*data_pointer] = C::default(),
+        FlatInstr::SetCell(value) => tape[*data_pointer] = C::from_u32(*value),
+        FlatInstr::ScanCells(direction) => {
+            *data_pointer = scan_for_zero(tape, *data_pointer, *direction, mode)?;
+        }
+        FlatInstr::ClearRange(len) => {
+            let end = *data_pointer + *len as usize;
+            if end <= tape.len() {
+                tape[*data_pointer..end].fill(C::default());
+                *data_pointer = end - 1;
+            } else {
+                match mode {
+                    // a part at the start of the tape, or all of it if the range is longer
+                    TapeMode::Wrap if end - tape.len() >= *data_pointer => tape.fill(C::default()),
+                    TapeMode::Wrap => {
+                        let len = tape.len();
+                        tape[*data_pointer..].fill(C::default());
+                        tape[..end - len].fill(C::default());
+                    }
+                    TapeMode::Clamp => tape[*data_pointer..].fill(C::default()),
+                    // nothing is cleared, like for `MultiplyMove`
+                    TapeMode::Error => return Err(RuntimeError::PointerOutOfBounds(tape.len() as i64, 0)),
+                }
+                *data_pointer = offset_index(*data_pointer, *len as i32 - 1, tape.len(), mode)?;
+            }
+        }
+        FlatInstr::InfiniteLoop => if !tape[*data_pointer].is_zero() {
+            return Err(RuntimeError::InfiniteLoop(*instr_pointer - 1));
+        },
+        FlatInstr::MultiplyMove(targets) => {
+            let value = tape[*data_pointer];
+            if !value.is_zero() {
+                // a target off the tape stops the loop before any of it ran
+                if mode == TapeMode::Error {
+                    for (offset, _) in targets {
+                        offset_index(*data_pointer, *offset, tape.len(), mode)?;
+                    }
+                }
+                for (offset, multiplier) in targets {
+                    let target = offset_index(*data_pointer, *offset, tape.len(), mode)?;
+                    tape[target] = tape[target].wrapping_add(value.wrapping_mul(C::from_u32(*multiplier)));
+                }
+                tape[*data_pointer] = C::default();
+            }
+        }
+        FlatInstr::MoveCell(offset) => {
+            let value = tape[*data_pointer];
+            if !value.is_zero() {
+                let target = offset_index(*data_pointer, *offset, tape.len(), mode)?;
+                tape[target] = tape[target].wrapping_add(value);
+                tape[*data_pointer] = C::default();
+            }
+        }
+        FlatInstr::Write => {
+            let value = tape[*data_pointer].to_u32();
+            if options.numeric_output {
+                write!(output, "{} ", value).map_err(output_error)?;
+            } else if std::mem::size_of::<C>() == 1 {
+                output.write_all(&[value as u8]).map_err(output_error)?;
+            } else {
+                let symbol = char::from_u32(value).unwrap_or(char::REPLACEMENT_CHARACTER);
+                write!(output, "{}", symbol).map_err(output_error)?;
+            }
+        }
+        FlatInstr::Read => {
+            if !options.buffer_across_reads {
+                output.flush().map_err(output_error)?;
+            }
+            let read = if options.utf8_input && std::mem::size_of::<C>() > 1 {
+                read_char(input).map(|symbol| symbol as u32)
+            } else {
+                let mut byte: [u8; 1] = [0; 1];
+                input.read_exact(&mut byte).map(|()| byte[0] as u32)
+            };
+            match read {
+                Ok(value) => tape[*data_pointer] = C::from_u32(value),
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => match options.eof {
+                    EofBehavior::Unchanged => (),
+                    EofBehavior::Zero => tape[*data_pointer] = C::default(),
+                    EofBehavior::NegOne => tape[*data_pointer] = C::MAX,
+                    EofBehavior::Error => return Err(RuntimeError::InputExhausted(*instr_pointer - 1)),
+                },
+                Err(e) => return Err(RuntimeError::InputError(e.kind())),
+            }
+        },
+        FlatInstr::Push => stack.push(tape[*data_pointer]),
+        FlatInstr::Pop => match stack.pop() {
+            Some(value) => tape[*data_pointer] = value,
+            None => return Err(RuntimeError::EmptyStack(*instr_pointer - 1)),
+        },
+        FlatInstr::JumpIfZero(target) => if tape[*data_pointer].is_zero() {
+            *instr_pointer = *target;
+        },
+        FlatInstr::JumpIfNonZero(target) => if !tape[*data_pointer].is_zero() {
+            *instr_pointer = *target;
+        },
+        FlatInstr::TapeState | FlatInstr::TapeWindow if options.visualize => {
+            write_tape_grid(tape, *data_pointer, output).map_err(output_error)?;
+        }
+        FlatInstr::TapeState => {
+            write_tape_state(tape, *data_pointer, options.ascii_table, output).map_err(output_error)?;
+        }
+        FlatInstr::TapeWindow => {
+            write_tape_window(tape, *data_pointer, TAPE_WINDOW_RADIUS, output).map_err(output_error)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn output_error(e: std::io::Error) -> RuntimeError {
+    RuntimeError::OutputError(e.kind())
+}
+
+/// writes a hexdump of `tape` up to its last nonzero cell, 16 cells per
+/// line, after a header line with the position of the data pointer.
+pub fn dump_tape<C: Cell, W: Write>(tape: &[C], data_pointer: usize, out: &mut W) -> std::io::Result<()> {
+    writeln!(out, "data pointer: {}", data_pointer)?;
+    let last_non_zero_index = tape.iter().rposition(|x| !x.is_zero()).map(|i| i + 1).unwrap_or(0);
+    let digits = std::mem::size_of::<C>() * 2;
+    for (line, cells) in tape[..last_non_zero_index].chunks(16).enumerate() {
+        write!(out, "{:08x} ", line * 16)?;
+        for cell in cells {
+            write!(out, " {:0digits$x}", cell, digits = digits)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// how many cells either side of the data pointer `#` shows
+pub const TAPE_WINDOW_RADIUS: usize = 8;
+
+/// reads one utf-8 encoded character. a broken sequence reads as U+FFFD,
+/// running out of input before the first byte is an `UnexpectedEof` error
+fn read_char<R: Read>(input: &mut R) -> std::io::Result<char> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes[..1])?;
+    let len = match bytes[0].leading_ones() {
+        0 => 1,
+        len @ 2..=4 => len as usize,
+        _ => return Ok(char::REPLACEMENT_CHARACTER),
+    };
+    for i in 1..len {
+        if input.read_exact(&mut bytes[i..=i]).is_err() || bytes[i] & 0xc0 != 0x80 {
+            return Ok(char::REPLACEMENT_CHARACTER);
+        }
+    }
+    Ok(std::str::from_utf8(&bytes[..len]).ok().and_then(|s| s.chars().next()).unwrap_or(char::REPLACEMENT_CHARACTER))
+}
+
+/// prints the values of the cells up to the last nonzero one, or up to the
+/// data pointer if that's further along, like `[72 >101 108]`. with `ascii`
+/// nonzero cells come with their character, like `[72='H' >105='i']`, and
+/// anything that isn't printable is escaped so it can't mess up the terminal
+pub fn write_tape_state<C: Cell, W: Write>(tape: &[C], data_pointer: usize, ascii: bool, out: &mut W) -> std::io::Result<()> {
+    let end = tape.iter().rposition(|x| !x.is_zero()).unwrap_or(0).max(data_pointer) + 1;
+    let cells: Vec<String> = tape[..end].iter().enumerate()
+        .map(|(i, cell)| {
+            let marker = if i == data_pointer { ">" } else { "" };
+            match cell.to_u32() {
+                0 => format!("{}0", marker),
+                value if ascii => format!("{}{}='{}'", marker, value, escape_char(value)),
+                value => format!("{}{}", marker, value),
+            }
+        })
+        .collect();
+    writeln!(out, "[{}]", cells.join(" "))
+}
+
+/// printable ascii as it is, bytes as `\x01`, and anything wider as `\u{263a}`
+fn escape_char(value: u32) -> String {
+    match u8::try_from(value) {
+        Ok(byte) => byte.escape_ascii().to_string(),
+        Err(_) => format!("\\u{{{:x}}}", value),
+    }
+}
+
+/// prints the cells up to `radius` cells either side of `data_pointer` as a
+/// row of indices over a row of values, with a `^` under the data pointer.
+pub fn write_tape_window<C: Cell, W: Write>(tape: &[C], data_pointer: usize, radius: usize, out: &mut W) -> std::io::Result<()> {
+    let window = data_pointer.saturating_sub(radius)..(data_pointer + radius + 1).min(tape.len());
+    let width = window.clone()
+        .map(|i| i.to_string().len().max(tape[i].to_string().len()))
+        .max()
+        .unwrap_or(1);
+
+    write!(out, "cell ")?;
+    for i in window.clone() {
+        write!(out, " {:>width$}", i, width = width)?;
+    }
+    write!(out, "\nvalue")?;
+    for i in window.clone() {
+        write!(out, " {:>width$}", tape[i], width = width)?;
+    }
+    let caret = 5 + (data_pointer - window.start + 1) * (width + 1);
+    writeln!(out, "\n{:>caret$}", "^", caret = caret)
+}
+
+/// 256 color palette entries from cold to hot, for tinting cells by their value
+const GRID_COLORS: [u8; 12] = [27, 33, 39, 45, 51, 49, 47, 82, 154, 226, 214, 196];
+
+/// clears the terminal and draws the tape up to its last nonzero cell (or the
+/// data pointer if that's further along) as a grid of 16 cells per row, using
+/// ANSI escape codes. zero cells are dimmed, every other cell is tinted by its
+/// value and the cell under the data pointer is drawn in reverse video.
+pub fn write_tape_grid<C: Cell, W: Write>(tape: &[C], data_pointer: usize, out: &mut W) -> std::io::Result<()> {
+    let end = tape.iter().rposition(|x| !x.is_zero()).unwrap_or(0).max(data_pointer) + 1;
+    let width = tape[..end].iter().map(|cell| cell.to_string().len()).max().unwrap_or(1);
+    let range = C::MAX.to_u32() as u64 + 1;
+
+    // home the cursor and clear the screen, so every draw replaces the last one
+    write!(out, "\x1b[H\x1b[2J")?;
+    for (line, cells) in tape[..end].chunks(16).enumerate() {
+        write!(out, "{:08x} ", line * 16)?;
+        for (i, cell) in cells.iter().enumerate() {
+            let color = if cell.is_zero() {
+                "\x1b[2m".to_string()
+            } else {
+                let shade = cell.to_u32() as u64 * GRID_COLORS.len() as u64 / range;
+                format!("\x1b[38;5;{}m", GRID_COLORS[shade as usize])
+            };
+            let highlight = if line * 16 + i == data_pointer { "\x1b[7m" } else { "" };
+            write!(out, " {}{}{:>width$}\x1b[0m", color, highlight, cell, width = width)?;
+        }
+        writeln!(out)?;
+    }
+    out.flush()
+}
+
+/// tokenizes, optimizes and flattens `source` for the tapes the `run`
+/// functions are given, which might not be all zeros, so without
+/// `ZEROED_TAPE_ONLY` passes
+fn compile_for_any_tape(source: &str) -> Result<Vec<FlatInstr>, Error> {
+    check_brackets(source)?;
+    let mut opcodes = tokenize(source);
+    let passes: Vec<Pass> = PASSES.iter().map(|(_, pass)| *pass).filter(|pass| !ZEROED_TAPE_ONLY.contains(pass)).collect();
+    optimize_with(&mut opcodes, &passes);
+    Ok(flatten(&parse(opcodes)?))
+}
+
+/// tokenizes, optimizes, parses and runs `source` on `tape`,
+/// starting at the first cell. input comes from stdin and output goes to stdout.
+pub fn run<C: Cell>(source: &str, tape: &mut [C]) -> Result<(), Error> {
+    run_at(source, tape, &mut 0)
+}
+
+/// runs `source` like `run` does, but starting at the cell `data_pointer`
+/// points to, and leaves it pointing wherever the program stopped. the tape
+/// can be one another program left behind, so several programs can run over
+/// the same memory one after another, without `ZEROED_TAPE_ONLY` passes
+pub fn run_at<C: Cell>(source: &str, tape: &mut [C], data_pointer: &mut usize) -> Result<(), Error> {
+    let program = compile_for_any_tape(source)?;
+    let mut input = std::io::stdin().lock();
+    let mut output = std::io::BufWriter::new(std::io::stdout().lock());
+    let result = execute(&program, tape, data_pointer, &mut input, &mut output, &Options::default(), None);
+    let flushed = output.flush();
+    result?;
+    Ok(flushed.map_err(output_error)?)
+}
+
+/// runs `source` like `run` does, calling `hook` before every instruction,
+/// see `execute_with_hook`
+pub fn run_with_hook<C: Cell, F: FnMut(usize, &FlatInstr, &[C], usize) -> ControlFlow<()>>(source: &str, tape: &mut [C], hook: F) -> Result<(), Error> {
+    let program = compile_for_any_tape(source)?;
+    let mut input = std::io::stdin().lock();
+    let mut output = std::io::BufWriter::new(std::io::stdout().lock());
+    let result = execute_with_hook(&program, tape, &mut 0, &mut input, &mut output, &Options::default(), hook);
+    let flushed = output.flush();
+    result?;
+    Ok(flushed.map_err(output_error)?)
+}
+
+/// runs `source` like `run` does and also returns everything it wrote to
+/// stdout, so output can be watched while it's generated and checked afterwards
+pub fn run_captured<C: Cell>(source: &str, tape: &mut [C]) -> Result<Vec<u8>, Error> {
+    let program = compile_for_any_tape(source)?;
+    let mut input = std::io::stdin().lock();
+    let mut captured = Vec::new();
+    let mut output = Tee::new(std::io::BufWriter::new(std::io::stdout().lock()), &mut captured);
+    let result = execute(&program, tape, &mut 0, &mut input, &mut output, &Options::default(), None);
+    let flushed = output.flush();
+    drop(output);
+    result?;
+    flushed.map_err(output_error)?;
+    Ok(captured)
+}
+
+/// a writer that writes everything to both of the ones it holds
+pub struct Tee<A, B> {
+    pub first: A,
+    pub second: B,
+}
+
+impl<A: Write, B: Write> Tee<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        Tee { first, second }
+    }
+}
+
+impl<A: Write, B: Write> Write for Tee<A, B> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // a short write to one of them would leave the two out of step
+        self.first.write_all(buf)?;
+        self.second.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.first.flush()?;
+        self.second.flush()
+    }
+}
+
+/// runs `source` like `run` does, on a fresh tape of 30000 8 bit cells, with
+/// `input` for `,` and returns everything the program wrote
+pub fn run_bytes(source: &str, input: &[u8]) -> Result<Vec<u8>, Error> {
+    check_brackets(source)?;
+    let mut opcodes = tokenize(source);
+    optimize_opcodes(&mut opcodes);
+    let program = flatten(&parse(opcodes)?);
+    let mut tape = vec![0u8; 30000];
+    let mut output = Vec::new();
+    execute(&program, &mut tape, &mut 0, &mut &input[..], &mut output, &Options::default(), None)?;
+    Ok(output)
+}
+
+/// `run_bytes` for text. output that isn't valid utf-8 has its broken
+/// sequences replaced with U+FFFD
+pub fn run_str(source: &str, input: &str) -> Result<String, Error> {
+    let output = run_bytes(source, input.as_bytes())?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
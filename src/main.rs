@@ -1,292 +1,1246 @@
-use std::io::Read;
-
-#[derive(Clone, Debug, PartialEq)]
-enum OpCode {
-    Move(i32),
-    IncrementPointer, DecrementPointer,
-    Increment, Decrement,
-    Add(u8), Sub(u8),
-    Write, Read,
-    LoopBegin, LoopEnd,
-    ResetCell,
-    ScanCells(bool),
-    TapeState,
-}
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::{env,iter,process,time::{Duration, Instant},fs::File,path::Path};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use brainfuckers::{bytecode, debugger::Debugger, format, repl::Repl, state::{self, CountingReader, VmState}, transpile, balance_brackets, check_brackets, strip_comments, remap, tokenize, tokenize_with_positions, optimize_logged, optimize_with, parse, flatten, find_out_of_bounds, any_instruction, execute, execute_growing, execute_with_hook, dump_tape, write_disassembly, write_ir, write_tape_window, Cell, CellOverflow, EofBehavior, FlatInstr, Instruction, OpCode, Options, Pass, Position, RuntimeError, Stats, TapeMode, CLAMP_UNSAFE, PASSES, SATURATE_UNSAFE, ZEROED_TAPE_ONLY};
+
+const USAGE: &str = "\
+usage: brainfuckers [run] <file path>... [-o <file path>] [options]
+       brainfuckers dump <file path>... [options]
+       brainfuckers disasm <file path>... [options]
+       brainfuckers check <file path>... [options]
+       brainfuckers compile <file path>... [-o <file path>]
+       brainfuckers transpile <file path>... [-o <file path>] [options]
+       brainfuckers repl [options]
+       brainfuckers fmt <file path> [-i] [--strip-comments]
+       brainfuckers selftest [options]
+
+commands:
+  run                          run the program, this is the default
+  dump                         print the optimized bytecode instead of running it
+  disasm                       print the optimized bytecode as a listing with the
+                               jump targets and where in the source each
+                               instruction came from
+  check                        only check that the program is well formed, and
+                               exit with an error if it isn't
+  compile                      write the optimized bytecode to a file that
+                               `run` and `dump` load without optimizing again
+  transpile                    write the optimized program as C source, or as a
+                               rust function with --lang rust
+  repl                         run every line typed in as a program of its own,
+                               keeping the tape between lines
+  fmt                          print the source with every loop level indented
+  selftest                     run a few built-in programs with the given
+                               options and check what they print
 
-#[derive(Clone, Debug)]
-enum Instruction {
-    Move(i32),
-    Add(u8), Sub(u8),
-    Write, Read,
-    Loop(Vec<Instruction>),
-    ResetCell,
-    ScanCells(bool),
-    TapeState,
+options:
+  -v                           print how long the program took to run
+  -vv                          also print the filtered source and the opcodes
+                               before and after optimizing
+  -vvv                         also print every change the optimizer made
+  -q, --quiet                  only print the program's own output and errors,
+                               no timing or warnings
+  --time-stderr                print how long the program took to stderr, so
+                               stdout only has the program's output
+  --tape-size <cells>          number of cells on the tape, defaults to 30000
+  --cell-size <8|16|32>        width of a cell in bits, defaults to 8
+  --eof <unchanged|zero|neg1|error>
+                               what `,` stores once input runs out, defaults to
+                               unchanged. error stops the program instead
+  --cell-overflow <wrap|saturate>
+                               what `+` and `-` do past the ends of a cell,
+                               defaults to wrap. saturate turns off the
+                               optimizer passes that merge them
+  --input <file path>          read input for `,` from a file instead of stdin
+  --comments                   skip /* block */ and ; line comments, even if they
+                               contain brainfuck symbols
+  --map <file path>            read aliases for the symbols from a file, one
+                               per line like `inc +`, and run them as those
+  --lenient                    ignore stray `]` and close every unclosed `[` at
+                               the end, instead of stopping with an error
+  --numeric-output             make `.` print the cell as a number and a space
+  --ascii-table                make `|` show the character of every nonzero cell
+  --bang-input                 treat everything after the first `!` in the
+                               source as input for `,`
+  --utf8-input                 with 16 or 32 bit cells, make `,` read a whole
+                               utf-8 character instead of a single byte
+  --dynamic-tape               start with a single cell and grow the tape whenever
+                               the data pointer moves past its end
+  --tape-mode <wrap|clamp|error>
+                               what happens when the data pointer leaves the tape,
+                               defaults to wrap. clamp turns off the optimizer
+                               passes that merge moves
+  --strict-bounds              same as --tape-mode error
+  --compat classic             behave like the original interpreter: 30000 8 bit
+                               cells that wrap, `,` leaves the cell alone at the
+                               end of input and leaving the tape is an error.
+                               options after it can still change those
+  --lint                       warn about moves that are sure to leave the tape
+                               before running the program
+  --max-steps <count>          stop with an error after running this many
+                               optimized instructions
+  --timeout <seconds>          stop with an error once the program has run this long
+  --dump-tape <file path>      write the final tape to a file
+  --init-tape <file path>      load the bytes of a file into the first cells before
+                               the program starts
+  --checkpoint-every <steps>   save the state of the program every this many
+                               optimized instructions, and when it's stopped
+                               by --max-steps or --timeout
+  --checkpoint <file path>     where checkpoints go, defaults to the source path
+                               with a .bfs extension
+  --resume <file path>         carry on from a saved state instead of starting over
+  --visualize                  make `|` and `#` redraw a colored grid of the tape
+  --catch-interrupt            on ctrl-c, print where the program was and what the
+                               tape looked like before exiting
+  --stats                      print how many times each instruction ran
+  --profile-loops              print the loops that ran the most iterations
+  --hist                       print how many iterations each entry into the
+                               busiest loops ran
+  --dump-ir                    print the parsed instruction tree before running
+  --trace                      print every instruction to stderr before it runs,
+                               with the data pointer and the value of its cell
+  --trace-range <from>:<to>    only trace while the data pointer is on one of
+                               these cells, ends included
+  --exit-from-cell0            exit with the value of the first cell once the
+                               program ends, its low 8 bits for wider cells
+  --jit                        compile the program to machine code before running
+                               it, for 8 bit cells on a tape that wraps. needs
+                               the crate built with the jit feature, on x86_64 linux
+  --debug-step                 run the program one instruction at a time, reading
+                               debugger commands from stdin. the program only
+                               gets input from --input then
+  -o, --output <file path>     where the program's output goes instead of stdout.
+                               for `compile` and `transpile` it defaults to the
+                               source path with a .bfc, .c or .rs extension
+  --output-buffer <bytes>      how much output is held back before it's written,
+                               defaults to 1 MiB with -o and 8 KiB otherwise
+  --lang <c|rust>              what `transpile` writes, defaults to c
+  -i                           make `fmt` replace the file instead of printing it
+  --strip-comments             make `fmt` leave out everything but the symbols
+  --no-opt                     don't optimize the program at all
+  -O0, -O1, -O2                how hard to optimize: -O0 is --no-opt, -O1 leaves
+                               out multiply, offsets and clear-range, and -O2,
+                               the default, runs every pass
+  --passes <list>              only run these optimizer passes, comma separated:
+                               reset, dead-stores, scan, multiply, run-length,
+                               set, moves, offsets, clear-range, known-zero,
+                               empty-loops, if
+  -h, --help                   print this message
+";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Command {
+    Run,
+    Dump,
+    Disasm,
+    Check,
+    Compile,
+    Transpile,
+    Repl,
+    Fmt,
+    SelfTest,
 }
 
-/// this turns the source code into a sequence of opcodes.
-/// should be somewhat easier to work with :3
-fn tokenize(source: &str) -> Vec<OpCode> {
-    source.chars().filter_map(|symbol| {
-        match symbol {
-            '>' => Some(OpCode::IncrementPointer),
-            '<' => Some(OpCode::DecrementPointer),
-            '+' => Some(OpCode::Increment),
-            '-' => Some(OpCode::Decrement),
-            '.' => Some(OpCode::Write),
-            ',' => Some(OpCode::Read),
-            '[' => Some(OpCode::LoopBegin),
-            ']' => Some(OpCode::LoopEnd),
-            '|' => Some(OpCode::TapeState), // additional, mostly for debug
-            _ => None,
-        }
-    }).collect()
+/// the languages `transpile` can write
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Language {
+    C,
+    Rust,
 }
 
-fn optimize_opcodes(opcodes: &mut Vec<OpCode>) {
-    let mut i = 0;
-    while i < opcodes.len() {
-        match opcodes[i] {
-            OpCode::LoopBegin if i + 2 < opcodes.len() 
-            && (opcodes[i + 1] == OpCode::Decrement 
-                    || opcodes[i + 1] == OpCode::Increment) 
-                && opcodes[i + 2] == OpCode::LoopEnd => {
-                opcodes.drain(i..i + 3);
-                opcodes.insert(i, OpCode::ResetCell);
-            }
+/// everything that was asked for on the command line
+struct Args {
+    command: Command,
+    file_path: String,
+    /// the files after the first one, which are read after it as if they
+    /// were all one file
+    more_file_paths: Vec<String>,
+    verbosity: u8,
+    quiet: bool,
+    time_stderr: bool,
+    tape_size: usize,
+    dynamic_tape: bool,
+    cell_size: u8,
+    options: Options,
+    show_stats: bool,
+    profile_loops: bool,
+    loop_histograms: bool,
+    catch_interrupt: bool,
+    lint: bool,
+    dump_ir: bool,
+    trace: bool,
+    /// the first and last cell to trace on
+    trace_range: Option<(usize, usize)>,
+    debug_step: bool,
+    jit: bool,
+    exit_from_cell0: bool,
+    input_path: Option<String>,
+    bang_input: bool,
+    comments: bool,
+    map_path: Option<String>,
+    lenient: bool,
+    dump_path: Option<String>,
+    init_tape_path: Option<String>,
+    checkpoint_every: Option<u64>,
+    checkpoint_path: Option<String>,
+    resume_path: Option<String>,
+    output_path: Option<String>,
+    output_buffer: Option<usize>,
+    language: Language,
+    in_place: bool,
+    strip_comments: bool,
+    passes: Vec<Pass>,
+}
 
-            OpCode::LoopBegin if i + 2 < opcodes.len() && (opcodes[i + 1] == 
-                OpCode::DecrementPointer 
-                    || opcodes[i + 1] == OpCode::IncrementPointer) 
-                && opcodes[i + 2] == OpCode::LoopEnd => {
-                opcodes.drain(i..i + 3);
-                opcodes.insert(i, OpCode::ScanCells(opcodes[i + 1] == 
-                    OpCode::IncrementPointer));
-            }
-            
-            OpCode::Increment | OpCode::Decrement => {
-                let mut count = 1;
-                let mut j = i + 1;
-                while j < opcodes.len() && opcodes[j] == opcodes[i] {
-                    count += 1;
-                    j += 1;
-                    }
+impl Args {
+    /// parses the arguments that come after the executable name
+    fn parse(args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args {
+            command: Command::Run,
+            file_path: String::new(),
+            more_file_paths: Vec::new(),
+            verbosity: 0,
+            quiet: false,
+            time_stderr: false,
+            tape_size: 30000,
+            dynamic_tape: false,
+            cell_size: 8,
+            options: Options::default(),
+            show_stats: false,
+            profile_loops: false,
+            loop_histograms: false,
+            catch_interrupt: false,
+            lint: false,
+            dump_ir: false,
+            trace: false,
+            trace_range: None,
+            debug_step: false,
+            jit: false,
+            exit_from_cell0: false,
+            input_path: None,
+            bang_input: false,
+            comments: false,
+            map_path: None,
+            lenient: false,
+            dump_path: None,
+            init_tape_path: None,
+            checkpoint_every: None,
+            checkpoint_path: None,
+            resume_path: None,
+            output_path: None,
+            output_buffer: None,
+            language: Language::C,
+            in_place: false,
+            strip_comments: false,
+            passes: PASSES.iter().map(|(_, pass)| *pass).collect(),
+        };
 
-                let replacement = match opcodes[i] {
-                    OpCode::Increment => OpCode::Add(count),
-                    OpCode::Decrement => OpCode::Sub(count),
-                    _ => unreachable!(),
-                };
+        let mut positional: Vec<String> = Vec::new();
+        let mut args = args.peekable();
 
-                opcodes.drain(i..j);
-                opcodes.insert(i, replacement);
-                
-            }
+        // the command is optional, running is the default
+        match args.peek().map(|arg| arg.as_str()) {
+            Some("run") => { args.next(); }
+            Some("dump") => { parsed.command = Command::Dump; args.next(); }
+            Some("disasm") => { parsed.command = Command::Disasm; args.next(); }
+            Some("check") => { parsed.command = Command::Check; args.next(); }
+            Some("compile") => { parsed.command = Command::Compile; args.next(); }
+            Some("transpile") => { parsed.command = Command::Transpile; args.next(); }
+            Some("repl") => { parsed.command = Command::Repl; args.next(); }
+            Some("fmt") => { parsed.command = Command::Fmt; args.next(); }
+            Some("selftest") => { parsed.command = Command::SelfTest; args.next(); }
+            _ => (),
+        }
 
-            OpCode::IncrementPointer | OpCode::DecrementPointer => {
-                let mut offset = 0;
-                let mut j = i;
-                while j < opcodes.len() {
-                    match opcodes[j] {
-                        OpCode::IncrementPointer => offset += 1,
-                        OpCode::DecrementPointer => offset -= 1,
-                        _ => break,
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => {
+                    print!("{}", USAGE);
+                    process::exit(0);
+                }
+                "-v" => parsed.verbosity = 1,
+                "-vv" => parsed.verbosity = 2,
+                "-vvv" => parsed.verbosity = 3,
+                flag if flag.len() > 4 && flag[1..].bytes().all(|b| b == b'v') => return Err(format!("unknown verbosity '{}', the levels are:\n  \
+                    -v    print how long the program took to run\n  \
+                    -vv   also print the filtered source and the opcodes before and after optimizing\n  \
+                    -vvv  also print every change the optimizer made", flag)),
+                "-q" | "--quiet" => parsed.quiet = true,
+                "--time-stderr" => parsed.time_stderr = true,
+                "--tape-size" => {
+                    let value = args.next().ok_or("--tape-size expects a number of cells")?;
+                    parsed.tape_size = match value.trim().parse() {
+                        Ok(0) => return Err("tape size must be at least 1 cell".to_string()),
+                        Ok(num) => num,
+                        Err(_) => return Err(format!("tape size must be a positive number, got '{}'", value)),
+                    };
+                }
+                "--cell-size" => {
+                    parsed.cell_size = match args.next().as_deref().map(str::trim) {
+                        Some("8") => 8,
+                        Some("16") => 16,
+                        Some("32") => 32,
+                        _ => return Err("--cell-size expects one of: 8, 16, 32".to_string()),
+                    };
+                }
+                "--eof" => {
+                    parsed.options.eof = match args.next().as_deref() {
+                        Some("unchanged") => EofBehavior::Unchanged,
+                        Some("zero") => EofBehavior::Zero,
+                        Some("neg1") => EofBehavior::NegOne,
+                        Some("error") => EofBehavior::Error,
+                        _ => return Err("--eof expects one of: unchanged, zero, neg1, error".to_string()),
+                    };
+                }
+                "--cell-overflow" => {
+                    parsed.options.cell_overflow = match args.next().as_deref() {
+                        Some("wrap") => CellOverflow::Wrap,
+                        Some("saturate") => CellOverflow::Saturate,
+                        _ => return Err("--cell-overflow expects one of: wrap, saturate".to_string()),
+                    };
+                }
+                "--input" => parsed.input_path = Some(args.next().ok_or("--input expects a file path")?),
+                "--bang-input" => parsed.bang_input = true,
+                "--comments" => parsed.comments = true,
+                "--map" => parsed.map_path = Some(args.next().ok_or("--map expects a file path")?),
+                "--lenient" => parsed.lenient = true,
+                "--numeric-output" => parsed.options.numeric_output = true,
+                "--ascii-table" => parsed.options.ascii_table = true,
+                "--utf8-input" => parsed.options.utf8_input = true,
+                "--dynamic-tape" => parsed.dynamic_tape = true,
+                "--tape-mode" => {
+                    parsed.options.tape_mode = match args.next().as_deref() {
+                        Some("wrap") => TapeMode::Wrap,
+                        Some("clamp") => TapeMode::Clamp,
+                        Some("error") => TapeMode::Error,
+                        _ => return Err("--tape-mode expects one of: wrap, clamp, error".to_string()),
+                    };
+                }
+                "--strict-bounds" => parsed.options.tape_mode = TapeMode::Error,
+                // the original interpreter doesn't say what leaving its tape does, so that's an error
+                "--compat" => match args.next().as_deref() {
+                    Some("classic") => {
+                        parsed.tape_size = 30000;
+                        parsed.dynamic_tape = false;
+                        parsed.cell_size = 8;
+                        parsed.options.cell_overflow = CellOverflow::Wrap;
+                        parsed.options.eof = EofBehavior::Unchanged;
+                        parsed.options.tape_mode = TapeMode::Error;
+                    }
+                    _ => return Err("--compat expects one of: classic".to_string()),
+                },
+                "--lint" => parsed.lint = true,
+                "--visualize" => parsed.options.visualize = true,
+                "--timeout" => {
+                    let value = args.next().ok_or("--timeout expects a number of seconds")?;
+                    parsed.options.timeout = match value.trim().parse().map(Duration::try_from_secs_f64) {
+                        Ok(Ok(timeout)) => Some(timeout),
+                        _ => return Err(format!("timeout must be a positive number of seconds, got '{}'", value)),
+                    };
+                }
+                "--max-steps" => {
+                    let value = args.next().ok_or("--max-steps expects a number of instructions")?;
+                    parsed.options.max_steps = match value.trim().parse() {
+                        Ok(num) => Some(num),
+                        Err(_) => return Err(format!("max steps must be a number, got '{}'", value)),
+                    };
+                }
+                "--dump-tape" => parsed.dump_path = Some(args.next().ok_or("--dump-tape expects a file path")?),
+                "--init-tape" => parsed.init_tape_path = Some(args.next().ok_or("--init-tape expects a file path")?),
+                "--checkpoint-every" => {
+                    let value = args.next().ok_or("--checkpoint-every expects a number of instructions")?;
+                    parsed.checkpoint_every = match value.trim().parse() {
+                        Ok(0) => return Err("checkpoints need at least 1 instruction between them".to_string()),
+                        Ok(num) => Some(num),
+                        Err(_) => return Err(format!("checkpoint interval must be a number, got '{}'", value)),
+                    };
+                }
+                "--checkpoint" => parsed.checkpoint_path = Some(args.next().ok_or("--checkpoint expects a file path")?),
+                "--resume" => parsed.resume_path = Some(args.next().ok_or("--resume expects a file path")?),
+                "--stats" => parsed.show_stats = true,
+                "--profile-loops" => parsed.profile_loops = true,
+                "--hist" => parsed.loop_histograms = true,
+                "--catch-interrupt" => {
+                    if !cfg!(unix) {
+                        return Err("--catch-interrupt only works on unix".to_string());
                     }
-                    j += 1;
+                    parsed.catch_interrupt = true;
+                    parsed.options.interrupt = Some(&INTERRUPTED);
                 }
-                if offset != 0 {
-                    opcodes.drain(i..j);
-                    opcodes.insert(i, OpCode::Move(offset));
+                "--jit" => {
+                    if !cfg!(feature = "jit") {
+                        return Err("--jit needs the crate built with the jit feature".to_string());
+                    }
+                    parsed.jit = true;
+                }
+                "--dump-ir" => parsed.dump_ir = true,
+                "--trace" => parsed.trace = true,
+                "--trace-range" => {
+                    let value = args.next().ok_or("--trace-range expects a range of cells like 0:9")?;
+                    let range = value.split_once(':').and_then(|(from, to)| Some((from.trim().parse().ok()?, to.trim().parse().ok()?)));
+                    parsed.trace_range = match range {
+                        Some((from, to)) if from <= to => Some((from, to)),
+                        _ => return Err(format!("trace range must be two cells like 0:9, the first one not after the second, got '{}'", value)),
+                    };
+                    parsed.trace = true;
+                }
+                "--debug-step" => parsed.debug_step = true,
+                "--exit-from-cell0" => parsed.exit_from_cell0 = true,
+                "--lang" => {
+                    parsed.language = match args.next().as_deref() {
+                        Some("c") => Language::C,
+                        Some("rust") => Language::Rust,
+                        _ => return Err("--lang expects one of: c, rust".to_string()),
+                    };
+                }
+                "-i" => parsed.in_place = true,
+                "--strip-comments" => parsed.strip_comments = true,
+                "--no-opt" | "-O0" => parsed.passes.clear(),
+                "-O1" => parsed.passes = PASSES.iter().map(|(_, pass)| *pass).filter(|pass| !O2_ONLY.contains(pass)).collect(),
+                "-O2" => parsed.passes = PASSES.iter().map(|(_, pass)| *pass).collect(),
+                "--passes" => {
+                    let list = args.next().ok_or("--passes expects a comma separated list of passes")?;
+                    parsed.passes = list.split(',').filter(|name| !name.is_empty()).map(|name| {
+                        PASSES.iter().find(|(known, _)| *known == name.trim()).map(|(_, pass)| *pass)
+                            .ok_or(format!("unknown optimizer pass '{}'", name))
+                    }).collect::<Result<_, _>>()?;
                 }
+                "-o" | "--output" => parsed.output_path = Some(args.next().ok_or_else(|| format!("{} expects a file path", arg))?),
+                "--output-buffer" => {
+                    let value = args.next().ok_or("--output-buffer expects a number of bytes")?;
+                    parsed.output_buffer = match value.trim().parse() {
+                        Ok(0) => return Err("the output buffer must hold at least 1 byte".to_string()),
+                        Ok(num) => Some(num),
+                        Err(_) => return Err(format!("output buffer size must be a positive number, got '{}'", value)),
+                    };
+                }
+                flag if flag.starts_with('-') && flag != "-" => return Err(format!("unknown option '{}'", flag)),
+                _ => positional.push(arg),
             }
-            _ => (),
         }
-        i += 1;
+
+        // ensure there is at least 1 positional argument: the file path.
+        // the repl reads its programs from stdin instead, and selftest has its own
+        if matches!(parsed.command, Command::Repl | Command::SelfTest) {
+            if !positional.is_empty() {
+                return Err(format!("{} doesn't take a file path", if parsed.command == Command::Repl { "repl" } else { "selftest" }));
+            }
+        } else if positional.is_empty() {
+            return Err("expected a file path".to_string());
+        } else {
+            parsed.more_file_paths = positional.split_off(1);
+            parsed.file_path = positional.remove(0);
+        }
+        if !parsed.more_file_paths.is_empty() {
+            if parsed.command == Command::Fmt {
+                return Err("fmt formats one file at a time".to_string());
+            }
+            if parsed.file_path == "-" || parsed.more_file_paths.iter().any(|path| path == "-") {
+                return Err("the program can only come from stdin on its own, not with other files".to_string());
+            }
+        }
+
+        if parsed.output_path.is_some() && matches!(parsed.command, Command::Dump | Command::Disasm | Command::Check | Command::Repl | Command::SelfTest) {
+            return Err("-o can't be used with dump, disasm, check, repl or selftest".to_string());
+        }
+        if (parsed.in_place || parsed.strip_comments) && parsed.command != Command::Fmt {
+            return Err("-i and --strip-comments only work with fmt".to_string());
+        }
+        if parsed.in_place && (parsed.output_path.is_some() || parsed.file_path == "-") {
+            return Err("-i writes back to the source file, so it needs a file path and no -o".to_string());
+        }
+        if parsed.exit_from_cell0 && parsed.command != Command::Run {
+            return Err("--exit-from-cell0 only works when running the program".to_string());
+        }
+        if parsed.jit {
+            if parsed.command != Command::Run {
+                return Err("--jit only works when running the program".to_string());
+            }
+            if parsed.cell_size != 8 || parsed.dynamic_tape || parsed.options.tape_mode != TapeMode::Wrap || parsed.options.cell_overflow != CellOverflow::Wrap {
+                return Err("--jit only knows 8 bit cells that wrap, on a tape of a fixed size that wraps".to_string());
+            }
+            if parsed.debug_step || parsed.trace || parsed.checkpoint_every.is_some() || parsed.resume_path.is_some() || parsed.show_stats || parsed.profile_loops || parsed.loop_histograms
+                || parsed.options.max_steps.is_some() || parsed.options.timeout.is_some() || parsed.catch_interrupt {
+                return Err("--jit can't be used with --debug-step, --trace, --checkpoint-every, --resume, --stats, --profile-loops, --hist, --max-steps, --timeout or --catch-interrupt".to_string());
+            }
+        }
+        if parsed.output_buffer.is_some() && parsed.command != Command::Run {
+            return Err("--output-buffer only works when running the program".to_string());
+        }
+        // nobody is waiting to see a prompt in a file
+        parsed.options.buffer_across_reads = parsed.output_path.is_some();
+        if parsed.map_path.is_some() && matches!(parsed.command, Command::Repl | Command::Fmt | Command::SelfTest) {
+            return Err("--map can't be used with repl, fmt or selftest".to_string());
+        }
+        if parsed.bang_input && parsed.input_path.is_some() {
+            return Err("--bang-input and --input can't both give the program its input".to_string());
+        }
+        if parsed.quiet {
+            parsed.verbosity = 0;
+        }
+        // a clamped pointer doesn't come back from `>` with `<`, and one that stops
+        // has to stop at the first move off the tape, so moves can't be merged
+        if matches!(parsed.options.tape_mode, TapeMode::Clamp | TapeMode::Error) {
+            parsed.passes.retain(|pass| !CLAMP_UNSAFE.contains(pass));
+        }
+        // `+` and `-` only cancel out when they wrap
+        if parsed.options.cell_overflow == CellOverflow::Saturate {
+            if matches!(parsed.command, Command::Compile | Command::Transpile) {
+                return Err("--cell-overflow saturate only works when running the program".to_string());
+            }
+            parsed.passes.retain(|pass| !SATURATE_UNSAFE.contains(pass));
+        }
+        // the generated function runs on whatever tape it's given
+        if parsed.command == Command::Transpile && parsed.language == Language::Rust {
+            parsed.passes.retain(|pass| !ZEROED_TAPE_ONLY.contains(pass));
+        }
+        if parsed.dynamic_tape && parsed.options.tape_mode != TapeMode::Wrap {
+            return Err("a dynamic tape grows instead, so it can't have a tape mode".to_string());
+        }
+        if parsed.dynamic_tape && (parsed.debug_step || parsed.checkpoint_every.is_some() || parsed.resume_path.is_some()) {
+            return Err("--dynamic-tape can't be used with --debug-step, --checkpoint-every or --resume".to_string());
+        }
+        if parsed.checkpoint_path.is_some() && parsed.checkpoint_every.is_none() {
+            return Err("--checkpoint needs --checkpoint-every to say how often to save".to_string());
+        }
+        if parsed.checkpoint_every.is_some() && parsed.checkpoint_path.is_none() && parsed.file_path == "-" {
+            return Err("reading the program from stdin needs a checkpoint path, pass one with --checkpoint".to_string());
+        }
+        if parsed.init_tape_path.is_some() && parsed.resume_path.is_some() {
+            return Err("--resume already has a tape, so it can't be loaded with --init-tape".to_string());
+        }
+        // the tape doesn't start out as zeros then
+        if parsed.init_tape_path.is_some() {
+            parsed.passes.retain(|pass| !ZEROED_TAPE_ONLY.contains(pass));
+        }
+        if parsed.trace && (parsed.debug_step || parsed.dynamic_tape || parsed.checkpoint_every.is_some() || parsed.resume_path.is_some() || parsed.show_stats || parsed.profile_loops || parsed.loop_histograms) {
+            return Err("--trace can't be used with --debug-step, --dynamic-tape, --checkpoint-every, --resume, --stats, --profile-loops or --hist".to_string());
+        }
+        if parsed.debug_step && (parsed.checkpoint_every.is_some() || parsed.resume_path.is_some()) {
+            return Err("--debug-step can't save or resume the state of a program".to_string());
+        }
+        if parsed.debug_step && parsed.file_path == "-" {
+            return Err("--debug-step reads commands from stdin, so the program can't come from there".to_string());
+        }
+
+        Ok(parsed)
+    }
+
+    /// the files the program came from, for messages about it
+    fn source_name(&self) -> String {
+        iter::once(&self.file_path).chain(&self.more_file_paths).cloned().collect::<Vec<_>>().join(", ")
     }
 }
 
-fn parse(opcodes: Vec<OpCode>) -> Vec<Instruction> {
-    let mut program: Vec<Instruction> = Vec::new();
-    let mut loop_stack = 0;
-    let mut loop_start = 0;
-
-    for (i, op) in opcodes.iter().enumerate() {
-        if loop_stack == 0 { // not inside a loop
-            let instr = match op {
-
-                OpCode::Move(offset)  => Some(Instruction::Move(*offset)),
-                OpCode::Add(count) => Some(Instruction::Add(*count)),
-                OpCode::Sub(count) => Some(Instruction::Sub(*count)),
-                OpCode::Write               => Some(Instruction::Write),
-                OpCode::Read                => Some(Instruction::Read),
-                OpCode::ResetCell           => Some(Instruction::ResetCell),
-                OpCode::ScanCells(bool) => Some(Instruction::ScanCells(*bool)),
-                
-                OpCode::LoopBegin => {
-                    loop_start = i;
-                    loop_stack += 1;
-                    None
-                },
+/// the passes -O1 leaves out, since they look at whole loops or runs of
+/// instructions and so take the longest
+const O2_ONLY: &[Pass] = &[Pass::Multiply, Pass::Offsets, Pass::ClearRange];
 
-                OpCode::LoopEnd => panic!("STRAY CLOSING BRACKET AT #{}!", i),
+/// a program `selftest` runs, with the input it gets and what it has to print
+struct SelfTest {
+    name: &'static str,
+    source: &'static str,
+    input: &'static [u8],
+    expected: &'static [u8],
+}
 
-                OpCode::IncrementPointer    => None, // Instruction::Move is used instead
-                OpCode::DecrementPointer    => None, // Instruction::Move is used instead
-                OpCode::Increment           => None,
-                OpCode::Decrement           => None,
-                OpCode::TapeState           => Some(Instruction::TapeState),
-            };
+const SELFTESTS: &[SelfTest] = &[
+    SelfTest { name: "hello", source: include_str!("../benches/programs/hello.bf"), input: b"", expected: b"Hello World!\n" },
+    // the file is nothing but the program, so it has to print the whole file
+    SelfTest { name: "quine", source: include_str!("../benches/programs/quine.bf"), input: b"", expected: include_bytes!("../benches/programs/quine.bf") },
+    SelfTest { name: "cat", source: include_str!("../benches/programs/cat.bf"), input: b"meow\nmeow\n", expected: b"meow\nmeow\n" },
+    SelfTest { name: "rot13", source: include_str!("../benches/programs/rot13.bf"), input: b"Hello, World! xyz", expected: b"Uryyb, Jbeyq! klm" },
+];
 
-            if let Some(instr) = instr {
-                program.push(instr);
-            }
+/// what the process exits with when the program couldn't be parsed, or
+/// failed while running. anything else that goes wrong exits with 1, bad
+/// arguments with 2 and a program stopped by --catch-interrupt with 130
+const EXIT_PARSE_ERROR: i32 = 3;
+const EXIT_RUNTIME_ERROR: i32 = 4;
 
-        } else {
-            match op { //inside a loop
-                OpCode::LoopBegin => loop_stack += 1,
-                OpCode::LoopEnd => {
-                    loop_stack -= 1;
+/// how much output is held back before it's written to a file with -o.
+/// programs that write files tend to write a lot, and nobody is watching
+/// the file fill up
+const FILE_OUTPUT_BUFFER: usize = 1 << 20;
 
-                    if loop_stack == 0 {
-                        program.push(Instruction::Loop(parse(opcodes[loop_start+1..i].to_vec())));
-                    }
-                },
-                _ => (),
-            }
+/// how many loops --profile-loops and --hist print
+const LOOPS_SHOWN: usize = 10;
+
+/// how many of the most common iteration counts --hist prints per loop
+const HISTOGRAM_BUCKETS: usize = 8;
+
+/// set once ctrl-c is pressed with --catch-interrupt
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// makes ctrl-c set `INTERRUPTED` so the program stops at the next check,
+/// and a second ctrl-c exit right away, e.g. while waiting for input.
+/// std can't catch signals, so this goes to libc, which std links anyway
+#[cfg(unix)]
+fn catch_interrupt() {
+    const SIGINT: i32 = 2;
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+        fn _exit(status: i32) -> !;
+    }
+    extern "C" fn on_interrupt(_: i32) {
+        if INTERRUPTED.swap(true, Ordering::Relaxed) {
+            // only async signal safe calls in here
+            unsafe { _exit(130) }
         }
     }
+    unsafe { signal(SIGINT, on_interrupt); }
+}
 
-    if loop_stack != 0 {
-        panic!("STRAY OPENING BRACKET AT #{}!", loop_start);
-    }
+#[cfg(not(unix))]
+fn catch_interrupt() {}
 
-    program
-}
+fn main() {
+    // get command line arguments
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    if args.catch_interrupt {
+        catch_interrupt();
+    }
+    if args.command == Command::Repl {
+        match args.cell_size {
+            8 => run_repl::<u8>(&args),
+            16 => run_repl::<u16>(&args),
+            _ => run_repl::<u32>(&args),
+        }
+        return;
+    }
+    if args.command == Command::SelfTest {
+        let passed = match args.cell_size {
+            8 => run_selftests::<u8>(&args),
+            16 => run_selftests::<u16>(&args),
+            _ => run_selftests::<u32>(&args),
+        };
+        println!("{}/{} passed", passed, SELFTESTS.len());
+        if passed < SELFTESTS.len() {
+            process::exit(1);
+        }
+        return;
+    }
+    let file_path = &args.file_path;
 
-/// executes a program that was previously parsed
-fn execute(instructions: &Vec<Instruction>, tape: &mut [u8;30000], data_pointer: &mut usize) {
-    for instr in instructions {
-        match instr {
-            
-            Instruction::Move(offset) => {
-                if *offset < 0 {
-                    *data_pointer = data_pointer.wrapping_sub(offset.unsigned_abs() as usize) % tape.len();
-                } else {
-                    *data_pointer = data_pointer.wrapping_add(*offset as usize) % tape.len();
-                }
-            }
-            Instruction::Add(count) => tape[*data_pointer] = 
-                tape[*data_pointer].wrapping_add(*count),
-            Instruction::Sub(count) => tape[*data_pointer] = 
-                tape[*data_pointer].wrapping_sub(*count),
-            Instruction::ResetCell => tape[*data_pointer] = 0,
-            Instruction::ScanCells(direction) => {
-                if *direction {
-                    while tape[*data_pointer] != 0 {
-                        *data_pointer += 1;
-                    }
-                } else {
-                    while tape[*data_pointer] != 0 {
-                        *data_pointer -= 1;
+    // read the content of the file, or of stdin if the path is "-".
+    // the program's own reads will then start out at end of input
+    let mut file_content = Vec::new();
+    if file_path == "-" {
+        if let Err(e) = io::stdin().read_to_end(&mut file_content) {
+            eprintln!("error reading program from stdin: {}", e);
+            process::exit(1);
+        }
+    } else {
+        // the other files carry on where the one before them ended, on a line of their own
+        for path in iter::once(file_path).chain(&args.more_file_paths) {
+            let mut content = Vec::new();
+            match File::open(path) {
+                Ok(mut file) => {
+                    if let Err(e) = file.read_to_end(&mut content) {
+                        eprintln!("error reading file: {}\nfile path: {}", e, path);
+                        process::exit(1);
                     }
                 }
-            }
-            Instruction::Write => print!("{}", tape[*data_pointer] as char),
-            Instruction::Read => {
-                let mut input: [u8; 1] = [0; 1];
-                std::io::stdin().read_exact(&mut input).expect("FAILED TO READ 'stdin'!");
-                tape[*data_pointer] = input[0];
-            },
-            Instruction::Loop(nested_instructions) => {
-                while tape[*data_pointer] != 0 {
-                    execute(&nested_instructions, tape, data_pointer)
+                Err(e) => {
+                    eprintln!("error opening file: {}\nfile path:{}", e, path);
+                    process::exit(1);
                 }
             }
-            Instruction::TapeState => {
-                let last_non_zero_index = tape.iter().rposition(|&x| x != 0).map(|i| i + 1).unwrap_or(0);
-                for i in 0..last_non_zero_index {print!("{} ", i);}println!();
+            if !args.more_file_paths.is_empty() && bytecode::is_bytecode(&content) {
+                eprintln!("compiled programs can't be combined with other files\nfile path: {}", path);
+                process::exit(1);
             }
+            if !file_content.is_empty() && !file_content.ends_with(b"\n") {
+                file_content.push(b'\n');
+            }
+            file_content.append(&mut content);
         }
     }
-}
 
-use std::{env,time::Instant,fs::File};
-
-fn main() {
-    // get command line arguments
-    let args: Vec<String> = env::args().collect();
-
-    // ensure there is at least 1 argument: the file path
-    if args.len() < 2 || args.len() > 3 {
-        eprintln!("usage: <program> <file path> [<debug mode>]");
+    if args.command == Command::Fmt {
+        format_file(&file_content, &args);
         return;
     }
 
-    // parse file path and optionally parse debug mode
-    let file_path = &args[1];
-    let debug_mode: u8 = if args.len() == 3 {
-        match args[2].trim().parse() {
-            Ok(num) => num,
-            Err(_) => {
-                eprintln!("debug mode must be a number between 0 and 255");
-                return;
+    // with --bang-input, source files carry the program's input after the first `!`
+    let mut bang_input = None;
+    if args.bang_input && !bytecode::is_bytecode(&file_content) {
+        if let Some(bang) = file_content.iter().position(|&byte| byte == b'!') {
+            bang_input = Some(file_content.split_off(bang + 1));
+            file_content.pop();
+        }
+    }
+
+    // compiled programs are already optimized, everything else is source code
+    let (program, source_map) = if bytecode::is_bytecode(&file_content) {
+        if args.command == Command::Transpile {
+            eprintln!("transpile needs brainfuck source, not a compiled program\nfile path: {}", file_path);
+            process::exit(1);
+        }
+        if args.options.cell_overflow == CellOverflow::Saturate {
+            eprintln!("compiled programs are optimized for wrapping cells, run the source with --cell-overflow saturate instead\nfile path: {}", file_path);
+            process::exit(1);
+        }
+        if args.options.tape_mode != TapeMode::Wrap {
+            eprintln!("compiled programs are optimized for a tape that wraps, run the source with --tape-mode clamp or error instead\nfile path: {}", file_path);
+            process::exit(1);
+        }
+        if args.init_tape_path.is_some() {
+            eprintln!("compiled programs are optimized for a tape of zeros, run the source with --init-tape instead\nfile path: {}", file_path);
+            process::exit(1);
+        }
+        match bytecode::decode(&file_content) {
+            // there's no source to point to
+            Ok(program) => (program, Vec::new()),
+            Err(e) => {
+                eprintln!("error loading compiled program: {}\nfile path: {}", e, file_path);
+                process::exit(EXIT_PARSE_ERROR);
             }
         }
     } else {
-        0
+        // only the ascii symbols matter, so comments can be in any encoding.
+        // positions count a broken sequence as one character
+        let source = String::from_utf8_lossy(&file_content);
+        let (program, source_map) = parse_source(&source, &args);
+        if args.command == Command::Transpile {
+            let mut transpiled = Vec::new();
+            let (written, extension) = match args.language {
+                Language::C => (transpile::to_c(&program, args.tape_size, args.cell_size, args.options.eof, &mut transpiled), "c"),
+                Language::Rust => (transpile::to_rust(&program, args.cell_size, args.options.eof, &mut transpiled), "rs"),
+            };
+            written.expect("FAILED TO WRITE TRANSPILED SOURCE!");
+            write_output(&output_path(&args, extension), &transpiled);
+            return;
+        }
+        if args.lint {
+            // a dynamic tape only ends on the left
+            let tape_size = if args.dynamic_tape { i64::MAX as usize } else { args.tape_size };
+            match find_out_of_bounds(&program, tape_size) {
+                Some(cell) if cell < 0 => eprintln!("warning: the data pointer reaches cell #{}, left of the first cell\nfile path: {}", cell, args.source_name()),
+                Some(cell) => eprintln!("warning: the data pointer reaches cell #{}, outside of the {} cell tape\nfile path: {}", cell, args.tape_size, args.source_name()),
+                None => (),
+            }
+        }
+        if args.command == Command::Run && !args.quiet {
+            warn_about_io(&program, &args);
+        }
+        (flatten(&program), source_map)
     };
 
-    // read the content of the file
-    let mut file_content = String::new();
-    match File::open(file_path) {
-        Ok(mut file) => {
-            if let Err(e) = file.read_to_string(&mut file_content) {
-                eprintln!("error reading file: {}\nfile path: {}", e,file_path);
-                return;
+    // getting this far means it parsed
+    if args.command == Command::Check {
+        return;
+    }
+
+    if args.command == Command::Compile {
+        write_output(&output_path(&args, "bfc"), &bytecode::encode(&program));
+        return;
+    }
+
+    if args.command == Command::Dump {
+        for (i, instr) in program.iter().enumerate() {
+            println!("{:04} {:?}", i, instr);
+        }
+        return;
+    }
+
+    if args.command == Command::Disasm {
+        write_disassembly(&program, &source_map, &mut io::stdout().lock()).expect("FAILED TO WRITE OUTPUT!");
+        return;
+    }
+
+    // the program reads from the input file or the end of the source file
+    // if there is one, stdin otherwise. `,` reads a byte at a time, so the
+    // file is buffered to not make a syscall for every one of them. stdin
+    // is buffered already
+    let mut input: Box<dyn Read> = match (&args.input_path, bang_input) {
+        (Some(path), _) => match File::open(path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => {
+                eprintln!("error opening input file: {}\nfile path: {}", e, path);
+                process::exit(1);
             }
+        },
+        (None, Some(bang_input)) => Box::new(io::Cursor::new(bang_input)),
+        // stdin belongs to the debugger
+        (None, None) if args.debug_step => Box::new(io::empty()),
+        (None, None) => Box::new(io::stdin().lock()),
+    };
+
+    // set up thhings and run program
+    match args.cell_size {
+        8 => run_program::<u8>(&program, source_map, &args, &mut input),
+        16 => run_program::<u16>(&program, source_map, &args, &mut input),
+        _ => run_program::<u32>(&program, source_map, &args, &mut input),
+    }
+}
+
+/// warns about programs that can't print anything, likely because a `.`
+/// was forgotten, and ones whose `,` can only ever see the end of input
+fn warn_about_io(program: &[Instruction], args: &Args) {
+    let prints = any_instruction(program, &|instr| matches!(instr, Instruction::Write | Instruction::TapeState | Instruction::TapeWindow));
+    if !prints && any_instruction(program, &|_| true) {
+        eprintln!("warning: the program has no `.`, so it never prints anything\nfile path: {}", args.source_name());
+    }
+    // stdin is either the source or the debugger's then
+    let no_input = args.input_path.is_none() && !args.bang_input && (args.file_path == "-" || args.debug_step);
+    if no_input && any_instruction(program, &|instr| matches!(instr, Instruction::Read)) {
+        eprintln!("warning: the program reads with `,`, but stdin is taken, so it only gets the end of input. pass --input to give it some\nfile path: {}", args.source_name());
+    }
+}
+
+/// where `compile` and `transpile` write to: the `-o` path, or the
+/// source path with its extension swapped for `extension`
+fn output_path(args: &Args, extension: &str) -> String {
+    match &args.output_path {
+        Some(path) => path.clone(),
+        None if args.file_path == "-" => {
+            eprintln!("reading the program from stdin needs an output path, pass one with -o");
+            process::exit(2);
         }
-        Err(e) => {
-            eprintln!("error opening file: {}\nfile path:{}", e,file_path);
-            return;
+        None => Path::new(&args.file_path).with_extension(extension).to_string_lossy().into_owned(),
+    }
+}
+
+fn write_output(path: &str, contents: &[u8]) {
+    if let Err(e) = std::fs::write(path, contents) {
+        eprintln!("error writing output: {}\nfile path: {}", e, path);
+        process::exit(1);
+    }
+}
+
+/// prints the formatted source, or writes it somewhere else with -o or -i
+fn format_file(file_content: &[u8], args: &Args) {
+    if bytecode::is_bytecode(file_content) {
+        eprintln!("fmt needs brainfuck source, not a compiled program\nfile path: {}", args.file_path);
+        process::exit(1);
+    }
+    // comments are written back as they are, so they can't be lossy
+    let source = match std::str::from_utf8(file_content) {
+        Ok(source) => source.into(),
+        Err(_) if args.strip_comments => String::from_utf8_lossy(file_content),
+        Err(_) => {
+            eprintln!("fmt keeps comments, so the source has to be utf-8. --strip-comments drops them instead\nfile path: {}", args.file_path);
+            process::exit(1);
+        }
+    };
+    let formatted = format::format_source(&source, !args.strip_comments);
+    match &args.output_path {
+        Some(path) => write_output(path, formatted.as_bytes()),
+        None if args.in_place => write_output(&args.file_path, formatted.as_bytes()),
+        None => print!("{}", formatted),
+    }
+}
+
+/// filters, optimizes and parses brainfuck source code. also returns where
+/// in the source every instruction of the flattened program came from
+fn parse_source(file_content: &str, args: &Args) -> (Vec<Instruction>, Vec<Position>) {
+    let mapped;
+    let file_content = match &args.map_path {
+        Some(path) => {
+            mapped = remap(file_content, &load_map(path));
+            &mapped
         }
+        None => file_content,
+    };
+    let stripped;
+    let file_content = if args.comments {
+        stripped = strip_comments(file_content);
+        &stripped
+    } else {
+        file_content
+    };
+
+    // before filtering, so bracket errors know their line and column.
+    // --lenient fixes them up later instead
+    match check_brackets(file_content) {
+        Err(e) if !args.lenient => {
+            eprintln!("error parsing program: {}\nfile path: {}", e, args.source_name());
+            process::exit(EXIT_PARSE_ERROR);
+        }
+        _ => (),
     }
 
     // filter the file content to include only the specified symbols
-    let allowed_symbols = "><+-.,[]|";
+    let allowed_symbols = if cfg!(feature = "stack") { "><+-.,[]|#:;" } else { "><+-.,[]|#" };
     let filtered_content: String = file_content.chars()
         .filter(|c| allowed_symbols.contains(*c))
         .collect();
-    // print the filtered content and debug mode
-    if debug_mode == 1u8 {
+    // print the filtered content
+    if args.verbosity >= 2 {
         println!("filtered content: {}", filtered_content);
-        println!("debug mode: {}", debug_mode);
+        println!("filtered out {} of {} characters", file_content.chars().count() - filtered_content.chars().count(), file_content.chars().count());
+    }
+    // probably the wrong file
+    if filtered_content.is_empty() && !args.quiet {
+        eprintln!("warning: no brainfuck symbols found, the program does nothing\nfile path: {}", args.source_name());
+    }
 
+    // turn the source code into a vector of opcodes, each with where it came from.
+    // everything but the symbols is skipped here too, so lines still add up
+    let mut tokens = tokenize_with_positions(file_content);
+    if args.lenient {
+        let fixed = balance_brackets(&mut tokens);
+        if fixed > 0 && !args.quiet {
+            eprintln!("warning: dropped or closed {} unmatched brackets\nfile path: {}", fixed, args.source_name());
         }
-    let source_code = filtered_content;
-
-    // turn the source code into a vector of opcodes
-    let mut opcodes = tokenize(&source_code);
+    }
+    // keep the unoptimized opcodes around only if they get printed
+    let original_opcodes = (args.verbosity >= 2).then(|| tokens.iter().map(|(op, _)| op.clone()).collect::<Vec<_>>());
 
-    optimize_opcodes(&mut opcodes);
-    if debug_mode == 1u8 {
+    if args.verbosity >= 3 {
+        println!("optimizer log:");
+        for rewrite in optimize_logged(&mut tokens, &args.passes) {
+            let name = PASSES.iter().find(|(_, pass)| *pass == rewrite.pass).map_or("?", |(name, _)| name);
+            let at = rewrite.removed.first().or(rewrite.inserted.first()).map(|(_, position)| *position).expect("A REWRITE ALWAYS CHANGES SOMETHING!");
+            let ops = |tokens: &[(OpCode, Position)]| if tokens.is_empty() { "nothing".to_string() } else { tokens.iter().map(|(op, _)| format!("{:?}", op)).collect::<Vec<_>>().join(" ") };
+            println!("round {} {} at {}: {} -> {}", rewrite.round, name, at, ops(&rewrite.removed), ops(&rewrite.inserted));
+        }
+    } else {
+        optimize_with(&mut tokens, &args.passes);
+    }
+    let (opcodes, mut source_map): (Vec<OpCode>, Vec<Position>) = tokens.into_iter().unzip();
+    // the end of an `If` doesn't become an instruction
+    let mut ops = opcodes.iter();
+    source_map.retain(|_| ops.next() != Some(&OpCode::IfEnd));
+    if let Some(original_opcodes) = original_opcodes {
         println!("original Opcodes:");
-        println!("{:?}",&opcodes);
+        println!("{:?}",&original_opcodes);
         println!("optimized Opcodes:");
         println!("{:?}",&opcodes);
     }
 
     // parse opcodes into a program / list of instructions
-    let program = parse(opcodes);
+    let program = match parse(opcodes) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("error parsing program: {}\nfile path: {}", e, args.source_name());
+            process::exit(EXIT_PARSE_ERROR);
+        }
+    };
+    if args.dump_ir {
+        println!("instruction tree:");
+        write_ir(&program, &mut io::stdout().lock()).expect("FAILED TO WRITE STDOUT!");
+    }
+    (program, source_map)
+}
 
-    // set up thhings and run program
-    let mut tape = [0u8; 30000];
-    let mut data_pointer = 0;
+/// runs every program in `SELFTESTS` on a fresh tape of cells of type `C`,
+/// optimized and run the way the arguments say, and prints how each one
+/// went. they all expect `,` to leave the cell alone at the end of input,
+/// so that's the one option they ignore. returns how many passed
+fn run_selftests<C: Cell>(args: &Args) -> usize {
+    let options = Options { eof: EofBehavior::Unchanged, ..args.options.clone() };
+    let mut passed = 0;
+    for test in SELFTESTS {
+        let mut opcodes = tokenize(test.source);
+        optimize_with(&mut opcodes, &args.passes);
+        let program = flatten(&parse(opcodes).expect("THE BUILT-IN PROGRAMS ARE WELL FORMED!"));
+        let mut output = Vec::new();
+        let result = if args.dynamic_tape {
+            execute_growing(&program, &mut vec![C::default()], &mut 0, &mut &test.input[..], &mut output, &options, None)
+        } else {
+            execute(&program, &mut vec![C::default(); args.tape_size], &mut 0, &mut &test.input[..], &mut output, &options, None)
+        };
+        match result {
+            Ok(()) if output == test.expected => {
+                println!("{:<8} ok", test.name);
+                passed += 1;
+            }
+            Ok(()) => println!("{:<8} FAILED: expected {:?}, got {:?}", test.name, String::from_utf8_lossy(test.expected), String::from_utf8_lossy(&output)),
+            Err(e) => println!("{:<8} FAILED: {}", test.name, e),
+        }
+    }
+    passed
+}
+
+/// reads the aliases in the --map file at `path`, every line an alias and the
+/// symbol it stands for with whitespace between them. empty lines are skipped
+fn load_map(path: &str) -> Vec<(String, char)> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("error reading map: {}\nfile path: {}", e, path);
+            process::exit(1);
+        }
+    };
+    let mut aliases: Vec<(String, char)> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let alias = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [] => continue,
+            [alias, _] if aliases.iter().any(|(known, _)| known == alias) => Err(format!("`{}` is mapped more than once", alias)),
+            [alias, symbol] => match symbol.chars().collect::<Vec<_>>()[..] {
+                [symbol] if !tokenize(&symbol.to_string()).is_empty() => Ok((alias.to_string(), symbol)),
+                _ => Err(format!("`{}` isn't a brainfuck symbol", symbol)),
+            },
+            _ => Err("expected an alias and the symbol it stands for, like `inc +`".to_string()),
+        };
+        match alias {
+            Ok(alias) => aliases.push(alias),
+            Err(e) => {
+                eprintln!("error reading map: {}, at line {}\nfile path: {}", e, i + 1, path);
+                process::exit(1);
+            }
+        }
+    }
+    aliases
+}
+
+/// runs the repl on a fresh tape of cells of type `C`. stdin is where the
+/// lines come from, so `,` only reads from the --input file
+fn run_repl<C: Cell>(args: &Args) {
+    let mut input: Box<dyn Read> = match &args.input_path {
+        Some(path) => match File::open(path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => {
+                eprintln!("error opening input file: {}\nfile path: {}", e, path);
+                process::exit(1);
+            }
+        },
+        None => Box::new(io::empty()),
+    };
+    let mut tape = initial_tape::<C>(args, args.tape_size);
+    Repl::new(io::stdin().lock(), args.passes.clone())
+        .run(&mut tape, &mut 0, &mut input, &mut io::stdout().lock(), &args.options);
+}
+
+/// a tape of `size` cells of type `C`, with the bytes of the --init-tape
+/// file in the first ones. a dynamic tape is made as long as the file instead
+fn initial_tape<C: Cell>(args: &Args, size: usize) -> Vec<C> {
+    let Some(path) = &args.init_tape_path else {
+        return vec![C::default(); size];
+    };
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("error reading initial tape: {}\nfile path: {}", e, path);
+            process::exit(1);
+        }
+    };
+    if bytes.len() > size && !args.dynamic_tape {
+        eprintln!("the initial tape has {} bytes, but the tape only has {} cells\nfile path: {}", bytes.len(), size, path);
+        process::exit(1);
+    }
+    let mut tape: Vec<C> = bytes.iter().map(|&byte| C::from_u32(byte as u32)).collect();
+    tape.resize(size.max(tape.len()), C::default());
+    tape
+}
+
+/// compiles `program` with the jit and runs it, see `JitProgram::run`
+#[cfg(feature = "jit")]
+fn run_jitted<C: Cell>(program: &[FlatInstr], tape: &mut [C], data_pointer: &mut usize, input: &mut impl Read, output: &mut impl Write, options: &Options) -> Result<(), RuntimeError> {
+    match brainfuckers::jit::JitProgram::compile(program, tape.len()) {
+        Ok(jitted) => jitted.run(tape, data_pointer, input, output, options),
+        Err(e) => {
+            eprintln!("error compiling program to machine code: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "jit"))]
+fn run_jitted<C: Cell>(_: &[FlatInstr], _: &mut [C], _: &mut usize, _: &mut impl Read, _: &mut impl Write, _: &Options) -> Result<(), RuntimeError> {
+    unreachable!("--JIT IS TURNED DOWN WITHOUT THE FEATURE!");
+}
+
+/// runs `program` on a fresh tape of cells of type `C`, or on the tape of
+/// the state given with --resume
+fn run_program<C: Cell>(program: &[FlatInstr], source_map: Vec<Position>, args: &Args, input: &mut impl Read) {
+    let mut input = CountingReader { inner: input, read: 0 };
+    let input = &mut input;
+    let mut state = match &args.resume_path {
+        Some(path) => match state::load_state::<C>(path, program) {
+            Ok(state) => state,
+            Err(e) => {
+                eprintln!("error loading state: {}\nfile path: {}", e, path);
+                process::exit(1);
+            }
+        },
+        None if args.dynamic_tape => VmState { tape: initial_tape(args, 1), ..VmState::new(0) },
+        None => VmState { tape: initial_tape(args, args.tape_size), ..VmState::new(0) },
+    };
+    // the input the saved run already read is skipped, so reads carry on where they left off
+    if let Err(e) = io::copy(&mut input.by_ref().take(state.input_read), &mut io::sink()) {
+        eprintln!("error skipping input: {}", e);
+        process::exit(1);
+    }
+    let checkpoint_path = args.checkpoint_every.map(|_| match &args.checkpoint_path {
+        Some(path) => path.clone(),
+        None => Path::new(&args.file_path).with_extension("bfs").to_string_lossy().into_owned(),
+    });
 
     let start_time = Instant::now();
-    
-    execute(&program, &mut tape, &mut data_pointer);
-    
+
+    // binary output is easier to keep out of the terminal with -o.
+    // a resumed run adds to what the saved run already wrote
+    let (sink, buffer): (Box<dyn Write>, usize) = match &args.output_path {
+        Some(path) => match File::options().write(true).create(true).append(args.resume_path.is_some()).truncate(args.resume_path.is_none()).open(path) {
+            Ok(file) => (Box::new(file), args.output_buffer.unwrap_or(FILE_OUTPUT_BUFFER)),
+            Err(e) => {
+                eprintln!("error creating output file: {}\nfile path: {}", e, path);
+                process::exit(1);
+            }
+        },
+        None => (Box::new(io::stdout().lock()), args.output_buffer.unwrap_or(8 * 1024)),
+    };
+    let mut output = BufWriter::with_capacity(buffer, sink);
+    let mut stats = Stats::default();
+    let collect_stats = args.show_stats || args.profile_loops || args.loop_histograms;
+    // the output so far is flushed first, since a resumed run won't write it again
+    let save = |state: &VmState<C>, output: &mut BufWriter<Box<dyn Write>>| {
+        let path = checkpoint_path.as_ref().expect("ONLY SAVING WITH A CHECKPOINT PATH!");
+        if let Err(e) = output.flush() {
+            eprintln!("error writing output: {}", e);
+            process::exit(1);
+        }
+        if let Err(e) = state::save_state(state, program, path) {
+            eprintln!("error saving state: {}\nfile path: {}", e, path);
+            process::exit(1);
+        }
+    };
+    let result = if args.debug_step {
+        // the debugger talks on stderr so its chatter stays out of the program's output
+        let mut debugger = Debugger::new(io::stdin().lock(), io::stderr().lock()).with_source_map(source_map.clone());
+        debugger.run(program, &mut state.tape, &mut state.data_pointer, input, &mut output, &args.options)
+    } else if let Some(every) = args.checkpoint_every {
+        let result = state.run(program, input, &mut output, &args.options, collect_stats.then_some(&mut stats), |state, output| {
+            if state.steps % every == 0 {
+                save(state, output);
+            }
+        });
+        // stopped between two instructions, so it's safe to pick up from here
+        if matches!(result, Err(RuntimeError::StepLimitReached(..) | RuntimeError::TimedOut(_) | RuntimeError::Interrupted(..))) {
+            save(&state, &mut output);
+        }
+        result
+    } else if args.resume_path.is_some() {
+        state.run(program, input, &mut output, &args.options, collect_stats.then_some(&mut stats), |_, _| ())
+    } else if args.trace {
+        // a line for every instruction is too much to write unbuffered
+        let mut log = BufWriter::new(io::stderr().lock());
+        let result = execute_with_hook(program, &mut state.tape, &mut state.data_pointer, input, &mut output, &args.options, |at, instr, tape, data_pointer| {
+            if args.trace_range.is_none_or(|(from, to)| (from..=to).contains(&data_pointer)) {
+                let traced = match source_map.get(at) {
+                    Some(position) => writeln!(log, "{:04} {:?} at {}: cell #{} = {}", at, instr, position, data_pointer, tape[data_pointer]),
+                    None => writeln!(log, "{:04} {:?}: cell #{} = {}", at, instr, data_pointer, tape[data_pointer]),
+                };
+                traced.expect("FAILED TO WRITE STDERR!");
+            }
+            ControlFlow::Continue(())
+        });
+        log.flush().expect("FAILED TO WRITE STDERR!");
+        result
+    } else if args.jit {
+        run_jitted(program, &mut state.tape, &mut state.data_pointer, input, &mut output, &args.options)
+    } else if args.dynamic_tape {
+        execute_growing(program, &mut state.tape, &mut state.data_pointer, input, &mut output, &args.options, collect_stats.then_some(&mut stats))
+    } else {
+        execute(program, &mut state.tape, &mut state.data_pointer, input, &mut output, &args.options, collect_stats.then_some(&mut stats))
+    };
+    if let Err(e) = output.flush() {
+        eprintln!("error writing output: {}", e);
+        process::exit(1);
+    }
+    // dump the tape even if the program failed, that's when it's most useful
+    if let Some(path) = &args.dump_path {
+        let dumped = File::create(path).and_then(|file| {
+            let mut file = BufWriter::new(file);
+            dump_tape(&state.tape, state.data_pointer, &mut file)?;
+            file.flush()
+        });
+        if let Err(e) = dumped {
+            eprintln!("error dumping tape: {}\nfile path: {}", e, path);
+        }
+    }
+    if let Err(e) = result {
+        match e {
+            RuntimeError::StepLimitReached(_, at) | RuntimeError::InfiniteLoop(at) | RuntimeError::PointerOutOfBounds(_, at) if at < source_map.len() => {
+                eprintln!("error running program: {}, at {}", e, source_map[at]);
+            }
+            // the skipped input of a resumed run was provided too
+            RuntimeError::InputExhausted(at) => {
+                let provided = input.read;
+                match source_map.get(at) {
+                    Some(position) => eprintln!("error running program: program read {} bytes but only {} were provided, at {}", provided + 1, provided, position),
+                    None => eprintln!("error running program: program read {} bytes but only {} were provided", provided + 1, provided),
+                }
+            }
+            // the whole point of catching it is seeing where the program was
+            RuntimeError::Interrupted(_, at) => {
+                match source_map.get(at) {
+                    Some(position) => eprintln!("{}, at {}", e, position),
+                    None => eprintln!("{}", e),
+                }
+                eprintln!("data pointer: {}", state.data_pointer);
+                write_tape_window(&state.tape, state.data_pointer, 8, &mut io::stderr().lock()).expect("FAILED TO WRITE STDERR!");
+                process::exit(130);
+            }
+            // the same as failing to flush it afterwards
+            RuntimeError::OutputError(kind) => {
+                eprintln!("error writing output: {}", kind);
+                process::exit(1);
+            }
+            _ => eprintln!("error running program: {}", e),
+        }
+        process::exit(EXIT_RUNTIME_ERROR);
+    }
+
     let elapsed_time = start_time.elapsed();
 
-    if debug_mode == 1u8 {println!("execution took: {:?}", elapsed_time);}
-}
\ No newline at end of file
+    if args.time_stderr {
+        eprintln!("execution took: {:?}", elapsed_time);
+    } else if args.verbosity >= 1 || args.show_stats {
+        println!("execution took: {:?}", elapsed_time);
+    }
+    if args.show_stats {
+        print!("{}", stats);
+        println!("instructions/sec: {:.0}", stats.total() as f64 / elapsed_time.as_secs_f64());
+        println!("highest cell used: #{} of {}", stats.highest_cell(), state.tape.len());
+    }
+    if args.profile_loops {
+        println!("hottest loops:");
+        for (start, iterations) in stats.hottest_loops(LOOPS_SHOWN) {
+            match source_map.get(start) {
+                Some(position) => println!("  {:04} at {:<24} {:>12} iterations", start, position.to_string(), iterations),
+                None => println!("  {:04} {:>12} iterations", start, iterations),
+            }
+        }
+    }
+    if args.loop_histograms {
+        println!("loop histograms:");
+        for (start, _) in stats.hottest_loops(LOOPS_SHOWN) {
+            let histogram = format_histogram(&stats.loop_histogram(start));
+            match source_map.get(start) {
+                Some(position) => println!("  {:04} at {:<24} {}", start, position.to_string(), histogram),
+                None => println!("  {:04} {}", start, histogram),
+            }
+        }
+    }
+    // some programs leave their result in the first cell, like a return value
+    if args.exit_from_cell0 {
+        process::exit((state.tape[0].to_u32() & 0xff) as i32);
+    }
+}
+
+/// the most common iteration counts of a loop, fewest iterations first,
+/// like `0 times 400x, 1 time 50x, 255 times 2x`
+fn format_histogram(histogram: &[(u64, u64)]) -> String {
+    let mut common = histogram.to_vec();
+    common.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    common.truncate(HISTOGRAM_BUCKETS);
+    common.sort();
+    let mut buckets: Vec<String> = common.iter()
+        .map(|(iterations, entries)| format!("{} time{} {}x", iterations, if *iterations == 1 { "" } else { "s" }, entries))
+        .collect();
+    if histogram.len() > HISTOGRAM_BUCKETS {
+        buckets.push(format!("and {} more", histogram.len() - HISTOGRAM_BUCKETS));
+    }
+    buckets.join(", ")
+}
@@ -0,0 +1,67 @@
+//! an interactive prompt that runs every line it reads as a program of its
+//! own, on a tape that's kept between lines, and prints the tape after each one.
+
+use std::io::{BufRead, Read, Write};
+
+use crate::{check_brackets, execute, flatten, optimize_with, parse, tokenize, write_tape_state, Cell, Options, Pass, ZEROED_TAPE_ONLY};
+
+/// reads lines from `lines` and optimizes them with `passes` before running
+/// them. every line but the first runs on a used tape, so `ZEROED_TAPE_ONLY`
+/// passes are left out
+pub struct Repl<B: BufRead> {
+    lines: B,
+    passes: Vec<Pass>,
+}
+
+impl<B: BufRead> Repl<B> {
+    pub fn new(lines: B, mut passes: Vec<Pass>) -> Self {
+        passes.retain(|pass| !ZEROED_TAPE_ONLY.contains(pass));
+        Repl { lines, passes }
+    }
+
+    /// runs lines until there are no more. the prompt, what the lines print
+    /// and the tape all go to `output`. a line with unbalanced brackets or
+    /// one that fails while running is reported, and the tape stays as the
+    /// line left it. the line `:reset` clears the tape and moves the data
+    /// pointer back to the first cell instead.
+    pub fn run<C: Cell, R: Read, W: Write>(&mut self, tape: &mut [C], data_pointer: &mut usize, input: &mut R, output: &mut W, options: &Options) {
+        let mut bytes = Vec::new();
+        loop {
+            write!(output, "> ").expect("FAILED TO WRITE OUTPUT!");
+            output.flush().expect("FAILED TO FLUSH OUTPUT!");
+            bytes.clear();
+            if self.lines.read_until(b'\n', &mut bytes).expect("FAILED TO READ LINE!") == 0 {
+                return;
+            }
+            // only the ascii symbols count, so the rest of a line doesn't have to be utf-8, like for files
+            let line = String::from_utf8_lossy(&bytes);
+
+            if line.trim() == ":reset" {
+                tape.fill(C::default());
+                *data_pointer = 0;
+                writeln!(output, "tape cleared").expect("FAILED TO WRITE OUTPUT!");
+                write_tape_state(tape, *data_pointer, options.ascii_table, output).expect("FAILED TO WRITE OUTPUT!");
+                continue;
+            }
+            if let Err(e) = check_brackets(&line) {
+                writeln!(output, "error: {}", e).expect("FAILED TO WRITE OUTPUT!");
+                continue;
+            }
+            let mut opcodes = tokenize(&line);
+            optimize_with(&mut opcodes, &self.passes);
+            let program = flatten(&parse(opcodes).expect("BRACKETS WERE ALREADY CHECKED!"));
+
+            // collected first so the tape always starts on a line of its own
+            let mut printed = Vec::new();
+            let result = execute(&program, tape, data_pointer, input, &mut printed, options, None);
+            output.write_all(&printed).expect("FAILED TO WRITE OUTPUT!");
+            if !printed.is_empty() && !printed.ends_with(b"\n") {
+                writeln!(output).expect("FAILED TO WRITE OUTPUT!");
+            }
+            if let Err(e) = result {
+                writeln!(output, "error: {}", e).expect("FAILED TO WRITE OUTPUT!");
+            }
+            write_tape_state(tape, *data_pointer, options.ascii_table, output).expect("FAILED TO WRITE OUTPUT!");
+        }
+    }
+}
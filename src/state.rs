@@ -0,0 +1,235 @@
+//! snapshots of a running program, so a long computation can be stopped
+//! and picked up again later where it left off.
+//!
+//! the file starts with `MAGIC` and a version byte, then a fingerprint of
+//! the program it belongs to, the cell width in bytes, and the step count,
+//! instruction pointer, data pointer and number of input bytes read, all
+//! as little endian u64. the tape follows as a u64 length and the cells,
+//! little endian and as wide as they are, and then the stack the same way.
+
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::{bytecode, check_limits, step, Cell, FlatInstr, Options, RuntimeError, Stats};
+
+/// what every state file starts with
+pub const MAGIC: &[u8; 3] = b"BFS";
+/// bumped whenever the encoding changes
+pub const VERSION: u8 = 2;
+
+/// everything a program needs to carry on running. the input isn't part of
+/// it, only how much of it was read, so the same input can be skipped
+/// ahead on resuming.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VmState<C> {
+    pub tape: Vec<C>,
+    pub data_pointer: usize,
+    /// the instruction that runs next
+    pub instr_pointer: usize,
+    /// instructions run so far, over every run that led up to this state
+    pub steps: u64,
+    /// bytes taken from the input so far
+    pub input_read: u64,
+    /// what `Push` left for `Pop`, see `step`
+    pub stack: Vec<C>,
+}
+
+impl<C: Cell> VmState<C> {
+    /// the state of a program that hasn't started yet
+    pub fn new(tape_size: usize) -> Self {
+        VmState { tape: vec![C::default(); tape_size], data_pointer: 0, instr_pointer: 0, steps: 0, input_read: 0, stack: Vec::new() }
+    }
+
+    /// runs `program` from wherever this state left off until it ends, the
+    /// same way `execute` would. `after_step` is called after every
+    /// instruction with the state as it is then and the output, e.g. to
+    /// flush the output and save the state, so they agree on what's been written.
+    ///
+    /// `options.max_steps` and `options.timeout` count from the start of
+    /// this call, not from the start of the program. a program stopped by
+    /// either of them or by `options.interrupt` leaves a state that can be
+    /// resumed.
+    pub fn run<R: Read, W: Write, F: FnMut(&Self, &mut W)>(&mut self, program: &[FlatInstr], input: &mut R, output: &mut W, options: &Options, mut stats: Option<&mut Stats>, mut after_step: F) -> Result<(), RuntimeError> {
+        let mut input = CountingReader { inner: input, read: self.input_read };
+        let mut steps = 0;
+        let deadline = options.timeout.map(|timeout| std::time::Instant::now() + timeout);
+        while let Some(instr) = program.get(self.instr_pointer) {
+            check_limits(options, steps, self.instr_pointer, deadline)?;
+            steps += 1;
+            if let Some(stats) = stats.as_deref_mut() {
+                stats.record(self.instr_pointer, instr);
+            }
+            let before = self.data_pointer;
+            step(program, &mut self.instr_pointer, &mut self.tape, &mut self.data_pointer, &mut self.stack, &mut input, output, options)?;
+            if let Some(stats) = stats.as_deref_mut() {
+                stats.record_cells(instr, before, self.data_pointer, self.tape.len(), options.tape_mode);
+            }
+            self.steps += 1;
+            self.input_read = input.read;
+            after_step(self, output);
+        }
+
+        Ok(())
+    }
+}
+
+/// counts the bytes read through it in `read`, to know how much input a
+/// state has used up, or how much there was
+pub struct CountingReader<'a, R> {
+    pub inner: &'a mut R,
+    pub read: u64,
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read += read as u64;
+        Ok(read)
+    }
+}
+
+/// why a state file couldn't be read back
+#[derive(Debug)]
+pub enum StateError {
+    /// the file couldn't be read at all
+    Io(io::Error),
+    /// the file doesn't start with `MAGIC`
+    NotAState,
+    /// the file was written by an incompatible version
+    UnsupportedVersion(u8),
+    /// the file ends before the whole state was read
+    Truncated,
+    /// the tape holds cells of this many bits instead
+    WrongCellSize(usize),
+    /// the state was saved while running another program
+    DifferentProgram,
+    /// the data pointer is outside the tape or the instruction pointer outside the program
+    Corrupt,
+}
+
+impl std::fmt::Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateError::Io(e) => write!(f, "{}", e),
+            StateError::NotAState => write!(f, "not a saved brainfuck state"),
+            StateError::UnsupportedVersion(v) => write!(f, "unsupported state version {} (expected {})", v, VERSION),
+            StateError::Truncated => write!(f, "state ends unexpectedly"),
+            StateError::WrongCellSize(bits) => write!(f, "the state was saved with {} bit cells", bits),
+            StateError::DifferentProgram => write!(f, "the state was saved while running a different program"),
+            StateError::Corrupt => write!(f, "the state points outside of its tape or program"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl From<io::Error> for StateError {
+    fn from(e: io::Error) -> Self {
+        StateError::Io(e)
+    }
+}
+
+/// fnv-1a over the program's bytecode, to tell programs apart
+fn fingerprint(program: &[FlatInstr]) -> u64 {
+    bytecode::encode(program).iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+pub fn encode<C: Cell>(state: &VmState<C>, program: &[FlatInstr]) -> Vec<u8> {
+    let width = std::mem::size_of::<C>();
+    let mut out = Vec::with_capacity(61 + (state.tape.len() + state.stack.len()) * width);
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.extend_from_slice(&fingerprint(program).to_le_bytes());
+    out.push(width as u8);
+    for value in [state.steps, state.instr_pointer as u64, state.data_pointer as u64, state.input_read] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    for cells in [&state.tape, &state.stack] {
+        out.extend_from_slice(&(cells.len() as u64).to_le_bytes());
+        for cell in cells {
+            out.extend_from_slice(&cell.to_u32().to_le_bytes()[..width]);
+        }
+    }
+    out
+}
+
+/// reads little endian values out of a byte slice
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.bytes.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// a u64 length and that many cells of `width` bytes
+    fn cells<C: Cell>(&mut self, width: usize) -> Result<Vec<C>, StateError> {
+        let len = self.u64()? as usize;
+        let cells = self.take(len.checked_mul(width).ok_or(StateError::Truncated)?)?;
+        Ok(cells.chunks_exact(width).map(|cell| {
+            let mut value = [0; 4];
+            value[..width].copy_from_slice(cell);
+            C::from_u32(u32::from_le_bytes(value))
+        }).collect())
+    }
+}
+
+/// reads back a state that was saved while running `program`
+pub fn decode<C: Cell>(bytes: &[u8], program: &[FlatInstr]) -> Result<VmState<C>, StateError> {
+    if !bytes.starts_with(MAGIC) {
+        return Err(StateError::NotAState);
+    }
+    let mut reader = Reader { bytes: &bytes[MAGIC.len()..] };
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(StateError::UnsupportedVersion(version));
+    }
+    if reader.u64()? != fingerprint(program) {
+        return Err(StateError::DifferentProgram);
+    }
+    let width = reader.u8()? as usize;
+    if width != std::mem::size_of::<C>() {
+        return Err(StateError::WrongCellSize(width * 8));
+    }
+
+    let steps = reader.u64()?;
+    let instr_pointer = reader.u64()? as usize;
+    let data_pointer = reader.u64()? as usize;
+    let input_read = reader.u64()?;
+    let tape = reader.cells(width)?;
+    let stack = reader.cells(width)?;
+    if data_pointer >= tape.len() || instr_pointer > program.len() {
+        return Err(StateError::Corrupt);
+    }
+
+    Ok(VmState { tape, data_pointer, instr_pointer, steps, input_read, stack })
+}
+
+/// writes `state` to the file at `path`, replacing it as a whole so an
+/// interrupted save doesn't leave a broken state behind
+pub fn save_state<C: Cell>(state: &VmState<C>, program: &[FlatInstr], path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    std::fs::write(&partial, encode(state, program))?;
+    std::fs::rename(&partial, path)
+}
+
+/// reads the state saved at `path` while running `program`
+pub fn load_state<C: Cell>(path: impl AsRef<Path>, program: &[FlatInstr]) -> Result<VmState<C>, StateError> {
+    decode(&std::fs::read(path)?, program)
+}
@@ -0,0 +1,313 @@
+//! turns the optimized instruction tree into source code for other
+//! languages, so hot programs can go through a real compiler.
+
+use std::io::{self, Write};
+
+use crate::{any_instruction, EofBehavior, Instruction, TAPE_WINDOW_RADIUS};
+
+/// writes a standalone C program that does the same thing as `program`.
+/// the data pointer wraps around the tape like it does in `execute`,
+/// and `.` writes the low byte of the cell.
+pub fn to_c<W: Write>(program: &[Instruction], tape_size: usize, cell_bits: u8, eof: EofBehavior, out: &mut W) -> io::Result<()> {
+    let cell_type = match cell_bits {
+        8 => "uint8_t",
+        16 => "uint16_t",
+        _ => "uint32_t",
+    };
+    writeln!(out, "#include <stdint.h>")?;
+    writeln!(out, "#include <stdio.h>")?;
+    if uses_stack(program) {
+        writeln!(out, "#include <stdlib.h>")?;
+    }
+    writeln!(out)?;
+    writeln!(out, "#define TAPE_SIZE {}", tape_size)?;
+    writeln!(out)?;
+    writeln!(out, "static {} tape[TAPE_SIZE];", cell_type)?;
+    if uses_stack(program) {
+        writeln!(out, "static {} *stack;", cell_type)?;
+        writeln!(out, "static size_t stack_len, stack_cap;")?;
+    }
+    writeln!(out)?;
+    writeln!(out, "int main(void) {{")?;
+    writeln!(out, "    size_t p = 0;")?;
+    writeln!(out)?;
+    write_c_at(program, 1, &CTarget { tape_size, cell_bits, eof }, out)?;
+    writeln!(out, "    return 0;")?;
+    writeln!(out, "}}")
+}
+
+/// whether the program has a `Push` or `Pop`, so the stack is needed
+fn uses_stack(program: &[Instruction]) -> bool {
+    any_instruction(program, &|instr| matches!(instr, Instruction::Push | Instruction::Pop))
+}
+
+/// what the generated C needs to know besides the instructions
+struct CTarget {
+    tape_size: usize,
+    cell_bits: u8,
+    eof: EofBehavior,
+}
+
+impl CTarget {
+    /// the pointer `offset` cells away from `p`, wrapped around the tape
+    fn pointer(&self, offset: i32) -> String {
+        let distance = (offset as i64).rem_euclid(self.tape_size as i64);
+        if distance == 0 {
+            "p".to_string()
+        } else if distance <= self.tape_size as i64 / 2 {
+            format!("(p + {}) % TAPE_SIZE", distance)
+        } else {
+            format!("(p + TAPE_SIZE - {}) % TAPE_SIZE", self.tape_size as i64 - distance)
+        }
+    }
+
+    fn cell(&self, offset: i32) -> String {
+        format!("tape[{}]", self.pointer(offset))
+    }
+
+    /// adds `count` to `target`, spelled as a subtraction when it really is one
+    fn add(&self, target: &str, count: u32, factor: &str) -> String {
+        if count > u32::MAX / 2 {
+            format!("{} -= {}{}u;", target, factor, count.wrapping_neg())
+        } else {
+            format!("{} += {}{}u;", target, factor, count)
+        }
+    }
+}
+
+fn write_c_at<W: Write>(program: &[Instruction], depth: usize, target: &CTarget, out: &mut W) -> io::Result<()> {
+    let indent = "    ".repeat(depth);
+    for instr in program {
+        match instr {
+            Instruction::Move(offset) => writeln!(out, "{}p = {};", indent, target.pointer(*offset))?,
+            Instruction::Add(count) => writeln!(out, "{}{}", indent, target.add("tape[p]", *count, ""))?,
+            Instruction::Sub(count) => writeln!(out, "{}{}", indent, target.add("tape[p]", count.wrapping_neg(), ""))?,
+            Instruction::AddAt(offset, count) => writeln!(out, "{}{}", indent, target.add(&target.cell(*offset), *count, ""))?,
+            Instruction::ResetCell => writeln!(out, "{}tape[p] = 0;", indent)?,
+            Instruction::SetCell(value) => writeln!(out, "{}tape[p] = {}u;", indent, value & (u32::MAX >> (32 - target.cell_bits as u32)))?,
+            Instruction::ScanCells(right) => {
+                writeln!(out, "{}while (tape[p]) p = {};", indent, target.pointer(if *right { 1 } else { -1 }))?
+            }
+            Instruction::MultiplyMove(targets) => {
+                for (offset, multiplier) in targets {
+                    writeln!(out, "{}{}", indent, target.add(&target.cell(*offset), *multiplier, "tape[p] * "))?;
+                }
+                writeln!(out, "{}tape[p] = 0;", indent)?;
+            }
+            Instruction::MoveCell(offset) => {
+                writeln!(out, "{}{} += tape[p];", indent, target.cell(*offset))?;
+                writeln!(out, "{}tape[p] = 0;", indent)?;
+            }
+            Instruction::Write => writeln!(out, "{}putchar(tape[p]);", indent)?,
+            Instruction::Read => {
+                writeln!(out, "{}fflush(stdout);", indent)?;
+                let store = match target.eof {
+                    EofBehavior::Unchanged => "if (c != EOF) tape[p] = c;",
+                    EofBehavior::Zero => "tape[p] = c == EOF ? 0 : c;",
+                    EofBehavior::NegOne => "tape[p] = c == EOF ? -1 : c;",
+                    EofBehavior::Error => "if (c == EOF) { fputs(\"read past the end of the input\\n\", stderr); return 1; } tape[p] = c;",
+                };
+                writeln!(out, "{}{{ int c = getchar(); {} }}", indent, store)?;
+            }
+            Instruction::TapeState => {
+                writeln!(out, "{}{{", indent)?;
+                writeln!(out, "{}    size_t end = TAPE_SIZE;", indent)?;
+                writeln!(out, "{}    while (end > p + 1 && !tape[end - 1]) end--;", indent)?;
+                writeln!(out, "{}    putchar('[');", indent)?;
+                writeln!(out, "{}    for (size_t i = 0; i < end; i++) printf(\"%s%s%lu\", i ? \" \" : \"\", i == p ? \">\" : \"\", (unsigned long)tape[i]);", indent)?;
+                writeln!(out, "{}    printf(\"]\\n\");", indent)?;
+                writeln!(out, "{}}}", indent)?;
+            }
+            Instruction::TapeWindow => {
+                // the same table `write_tape_window` prints
+                let radius = TAPE_WINDOW_RADIUS;
+                writeln!(out, "{}{{", indent)?;
+                writeln!(out, "{}    size_t lo = p < {} ? 0 : p - {}, hi = p + {} < TAPE_SIZE ? p + {} : TAPE_SIZE;", indent, radius, radius, radius + 1, radius + 1)?;
+                writeln!(out, "{}    int width = 1, w;", indent)?;
+                writeln!(out, "{}    char buf[16];", indent)?;
+                writeln!(out, "{}    for (size_t i = lo; i < hi; i++) {{", indent)?;
+                writeln!(out, "{}        if ((w = snprintf(buf, sizeof buf, \"%zu\", i)) > width) width = w;", indent)?;
+                writeln!(out, "{}        if ((w = snprintf(buf, sizeof buf, \"%lu\", (unsigned long)tape[i])) > width) width = w;", indent)?;
+                writeln!(out, "{}    }}", indent)?;
+                writeln!(out, "{}    printf(\"cell \");", indent)?;
+                writeln!(out, "{}    for (size_t i = lo; i < hi; i++) printf(\" %*zu\", width, i);", indent)?;
+                writeln!(out, "{}    printf(\"\\nvalue\");", indent)?;
+                writeln!(out, "{}    for (size_t i = lo; i < hi; i++) printf(\" %*lu\", width, (unsigned long)tape[i]);", indent)?;
+                writeln!(out, "{}    printf(\"\\n%*s\\n\", (int)(5 + (p - lo + 1) * (width + 1)), \"^\");", indent)?;
+                writeln!(out, "{}}}", indent)?;
+            }
+            Instruction::ClearRange(len) => {
+                writeln!(out, "{}for (size_t i = 0; i < {}; i++) tape[(p + i) % TAPE_SIZE] = 0;", indent, len)?;
+                writeln!(out, "{}p = {};", indent, target.pointer(*len as i32 - 1))?;
+            }
+            Instruction::InfiniteLoop => {
+                writeln!(out, "{}if (tape[p]) {{ fputs(\"empty loop started on a nonzero cell and would never end\\n\", stderr); return 1; }}", indent)?
+            }
+            Instruction::Push => {
+                writeln!(out, "{}if (stack_len == stack_cap && !(stack = realloc(stack, (stack_cap = stack_cap * 2 + 16) * sizeof *stack))) {{ fputs(\"out of memory for the stack\\n\", stderr); return 1; }}", indent)?;
+                writeln!(out, "{}stack[stack_len++] = tape[p];", indent)?;
+            }
+            Instruction::Pop => {
+                writeln!(out, "{}if (!stack_len) {{ fputs(\"popped an empty stack\\n\", stderr); return 1; }}", indent)?;
+                writeln!(out, "{}tape[p] = stack[--stack_len];", indent)?;
+            }
+            Instruction::Loop(body) => {
+                writeln!(out, "{}while (tape[p]) {{", indent)?;
+                write_c_at(body, depth + 1, target, out)?;
+                writeln!(out, "{}}}", indent)?;
+            }
+            Instruction::If(body) => {
+                writeln!(out, "{}if (tape[p]) {{", indent)?;
+                write_c_at(body, depth + 1, target, out)?;
+                writeln!(out, "{}}}", indent)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// writes a `pub fn run(tape: &mut [cell], ptr: &mut usize)` that does the
+/// same thing as `program`, reading stdin and writing stdout. the pointer
+/// wraps around the tape and `.` prints the cell the same way `execute` does.
+/// the tape it's given might not be all zeros, so leave out the
+/// `ZEROED_TAPE_ONLY` passes when optimizing `program`.
+pub fn to_rust<W: Write>(program: &[Instruction], cell_bits: u8, eof: EofBehavior, out: &mut W) -> io::Result<()> {
+    let cell_type = match cell_bits {
+        8 => "u8",
+        16 => "u16",
+        _ => "u32",
+    };
+    writeln!(out, "#[allow(unused)]")?;
+    writeln!(out, "pub fn run(tape: &mut [{}], ptr: &mut usize) {{", cell_type)?;
+    writeln!(out, "    use std::io::{{Read, Write}};")?;
+    writeln!(out, "    let len = tape.len() as i64;")?;
+    writeln!(out, "    let at = |ptr: usize, offset: i64| (ptr as i64 + offset).rem_euclid(len) as usize;")?;
+    writeln!(out, "    let mut input = std::io::stdin().lock();")?;
+    writeln!(out, "    let mut output = std::io::stdout().lock();")?;
+    if uses_stack(program) {
+        writeln!(out, "    let mut stack = Vec::new();")?;
+    }
+    writeln!(out)?;
+    write_rust_at(program, 1, &RustTarget { cell_type, cell_bits, eof }, out)?;
+    writeln!(out, "    output.flush().unwrap();")?;
+    writeln!(out, "}}")
+}
+
+/// what the generated rust needs to know besides the instructions
+struct RustTarget {
+    cell_type: &'static str,
+    cell_bits: u8,
+    eof: EofBehavior,
+}
+
+impl RustTarget {
+    fn cell(&self, offset: i32) -> String {
+        match offset {
+            0 => "tape[*ptr]".to_string(),
+            _ => format!("tape[at(*ptr, {})]", offset),
+        }
+    }
+
+    /// adds `count` (times the current cell, if `multiply` is set), truncated
+    /// to the cell width, to `target`. spelled as a subtraction when it really is one
+    fn add(&self, target: &str, count: u32, multiply: bool) -> String {
+        let mask = u32::MAX >> (32 - self.cell_bits as u32);
+        let count = count & mask;
+        let (method, count) = if count > mask / 2 {
+            ("wrapping_sub", count.wrapping_neg() & mask)
+        } else {
+            ("wrapping_add", count)
+        };
+        if multiply {
+            format!("{} = {}.{}(tape[*ptr].wrapping_mul({}));", target, target, method, count)
+        } else {
+            format!("{} = {}.{}({});", target, target, method, count)
+        }
+    }
+}
+
+fn write_rust_at<W: Write>(program: &[Instruction], depth: usize, target: &RustTarget, out: &mut W) -> io::Result<()> {
+    let indent = "    ".repeat(depth);
+    for instr in program {
+        match instr {
+            Instruction::Move(offset) => writeln!(out, "{}*ptr = at(*ptr, {});", indent, offset)?,
+            Instruction::Add(count) => writeln!(out, "{}{}", indent, target.add("tape[*ptr]", *count, false))?,
+            Instruction::Sub(count) => writeln!(out, "{}{}", indent, target.add("tape[*ptr]", count.wrapping_neg(), false))?,
+            Instruction::AddAt(offset, count) => writeln!(out, "{}{}", indent, target.add(&target.cell(*offset), *count, false))?,
+            Instruction::ResetCell => writeln!(out, "{}tape[*ptr] = 0;", indent)?,
+            Instruction::SetCell(value) => writeln!(out, "{}tape[*ptr] = {};", indent, value & (u32::MAX >> (32 - target.cell_bits as u32)))?,
+            Instruction::ScanCells(right) => {
+                writeln!(out, "{}while tape[*ptr] != 0 {{ *ptr = at(*ptr, {}); }}", indent, if *right { 1 } else { -1 })?
+            }
+            Instruction::MultiplyMove(targets) => {
+                for (offset, multiplier) in targets {
+                    writeln!(out, "{}{}", indent, target.add(&target.cell(*offset), *multiplier, true))?;
+                }
+                writeln!(out, "{}tape[*ptr] = 0;", indent)?;
+            }
+            Instruction::MoveCell(offset) => {
+                let cell = target.cell(*offset);
+                writeln!(out, "{}{} = {}.wrapping_add(tape[*ptr]);", indent, cell, cell)?;
+                writeln!(out, "{}tape[*ptr] = 0;", indent)?;
+            }
+            Instruction::Write if target.cell_bits == 8 => writeln!(out, "{}output.write_all(&[tape[*ptr]]).unwrap();", indent)?,
+            Instruction::Write => writeln!(out,
+                "{}write!(output, \"{{}}\", char::from_u32(tape[*ptr] as u32).unwrap_or(char::REPLACEMENT_CHARACTER)).unwrap();",
+                indent)?,
+            Instruction::Read => {
+                writeln!(out, "{}output.flush().unwrap();", indent)?;
+                writeln!(out, "{}let mut byte = [0u8];", indent)?;
+                let at_eof = match target.eof {
+                    EofBehavior::Unchanged => String::new(),
+                    EofBehavior::Zero => " else { tape[*ptr] = 0; }".to_string(),
+                    EofBehavior::NegOne => format!(" else {{ tape[*ptr] = {}::MAX; }}", target.cell_type),
+                    EofBehavior::Error => " else { panic!(\"read past the end of the input\"); }".to_string(),
+                };
+                writeln!(out, "{}if input.read_exact(&mut byte).is_ok() {{ tape[*ptr] = byte[0].into(); }}{}", indent, at_eof)?;
+            }
+            Instruction::TapeState => {
+                writeln!(out, "{}{{", indent)?;
+                writeln!(out, "{}    let end = tape.iter().rposition(|cell| *cell != 0).unwrap_or(0).max(*ptr) + 1;", indent)?;
+                writeln!(out, "{}    let cells: Vec<String> = tape[..end].iter().enumerate()", indent)?;
+                writeln!(out, "{}        .map(|(i, cell)| if i == *ptr {{ format!(\">{{}}\", cell) }} else {{ cell.to_string() }})", indent)?;
+                writeln!(out, "{}        .collect();", indent)?;
+                writeln!(out, "{}    writeln!(output, \"[{{}}]\", cells.join(\" \")).unwrap();", indent)?;
+                writeln!(out, "{}}}", indent)?;
+            }
+            Instruction::TapeWindow => {
+                // the same table `write_tape_window` prints
+                writeln!(out, "{}{{", indent)?;
+                writeln!(out, "{}    let window = ptr.saturating_sub({})..(*ptr + {}).min(tape.len());", indent, TAPE_WINDOW_RADIUS, TAPE_WINDOW_RADIUS + 1)?;
+                writeln!(out, "{}    let width = window.clone().map(|i| i.to_string().len().max(tape[i].to_string().len())).max().unwrap_or(1);", indent)?;
+                writeln!(out, "{}    write!(output, \"cell \").unwrap();", indent)?;
+                writeln!(out, "{}    for i in window.clone() {{ write!(output, \" {{:>width$}}\", i).unwrap(); }}", indent)?;
+                writeln!(out, "{}    write!(output, \"\\nvalue\").unwrap();", indent)?;
+                writeln!(out, "{}    for i in window.clone() {{ write!(output, \" {{:>width$}}\", tape[i]).unwrap(); }}", indent)?;
+                writeln!(out, "{}    writeln!(output, \"\\n{{:>caret$}}\", \"^\", caret = 5 + (*ptr - window.start + 1) * (width + 1)).unwrap();", indent)?;
+                writeln!(out, "{}}}", indent)?;
+            }
+            Instruction::ClearRange(len) => {
+                writeln!(out, "{}for i in 0..{} {{ tape[at(*ptr, i)] = 0; }}", indent, len)?;
+                writeln!(out, "{}*ptr = at(*ptr, {});", indent, *len as i64 - 1)?;
+            }
+            Instruction::InfiniteLoop => {
+                writeln!(out, "{}if tape[*ptr] != 0 {{ output.flush().unwrap(); panic!(\"empty loop started on a nonzero cell and would never end\"); }}", indent)?
+            }
+            Instruction::Push => writeln!(out, "{}stack.push(tape[*ptr]);", indent)?,
+            Instruction::Pop => {
+                writeln!(out, "{}tape[*ptr] = match stack.pop() {{ Some(value) => value, None => {{ output.flush().unwrap(); panic!(\"popped an empty stack\"); }} }};", indent)?
+            }
+            Instruction::Loop(body) => {
+                writeln!(out, "{}while tape[*ptr] != 0 {{", indent)?;
+                write_rust_at(body, depth + 1, target, out)?;
+                writeln!(out, "{}}}", indent)?;
+            }
+            Instruction::If(body) => {
+                writeln!(out, "{}if tape[*ptr] != 0 {{", indent)?;
+                write_rust_at(body, depth + 1, target, out)?;
+                writeln!(out, "{}}}", indent)?;
+            }
+        }
+    }
+    Ok(())
+}
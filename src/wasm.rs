@@ -0,0 +1,57 @@
+//! what a webassembly build of the crate exports to javascript, with the
+//! `wasm` feature. there's no wasm-bindgen to pass strings back and forth,
+//! so they go through the module's memory instead: javascript gets room for
+//! them from `bf_alloc`, writes them there, and reads the output back from
+//! where `bf_run` says it is. `web/index.html` does all of that.
+
+use crate::run_str;
+
+/// runs `source` like `run_str` does, and returns what it wrote, or the
+/// error it stopped with as `error: ...`
+pub fn run(source: &str, input: &str) -> String {
+    match run_str(source, input) {
+        Ok(output) => output,
+        Err(e) => format!("error: {}", e),
+    }
+}
+
+/// makes room for `len` bytes and returns where it starts
+#[no_mangle]
+pub extern "C" fn bf_alloc(len: usize) -> *mut u8 {
+    leak(vec![0; len])
+}
+
+/// gives back room that `bf_alloc` made, or that `bf_run` returned
+///
+/// # Safety
+///
+/// `start` and `len` have to be exactly what `bf_alloc` was called with and
+/// returned, or what `bf_run` returned and the length it wrote there plus 4
+#[no_mangle]
+pub unsafe extern "C" fn bf_free(start: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(start, 0, len));
+}
+
+/// runs the utf-8 source and input at `source` and `input`, see `run`.
+/// returns where the output is, as a little endian u32 length followed by
+/// that many bytes of utf-8
+///
+/// # Safety
+///
+/// `source` and `input` have to point to that many bytes, like ones
+/// written to room from `bf_alloc`
+#[no_mangle]
+pub unsafe extern "C" fn bf_run(source: *const u8, source_len: usize, input: *const u8, input_len: usize) -> *mut u8 {
+    let text = |start: *const u8, len: usize| String::from_utf8_lossy(std::slice::from_raw_parts(start, len)).into_owned();
+    let output = run(&text(source, source_len), &text(input, input_len));
+    let mut buffer = Vec::with_capacity(4 + output.len());
+    buffer.extend_from_slice(&(output.len() as u32).to_le_bytes());
+    buffer.extend_from_slice(output.as_bytes());
+    leak(buffer)
+}
+
+/// hands `buffer` over to javascript, to come back through `bf_free`
+fn leak(buffer: Vec<u8>) -> *mut u8 {
+    // `bf_free` only knows the length, so that has to be all of the capacity
+    Box::leak(buffer.into_boxed_slice()).as_mut_ptr()
+}
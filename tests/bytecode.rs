@@ -0,0 +1,45 @@
+use brainfuckers::bytecode::{decode, encode, DecodeError};
+use brainfuckers::{flatten, optimize_opcodes, parse, tokenize, FlatInstr};
+
+fn compiled(source: &str) -> Vec<FlatInstr> {
+    let mut opcodes = tokenize(source);
+    optimize_opcodes(&mut opcodes);
+    flatten(&parse(opcodes).unwrap())
+}
+
+#[test]
+fn every_instruction_survives_a_round_trip() {
+    let program = compiled("++[->+>+++<<]>[-]>[<+>-]<[>]<[<]+>>>-.,|[>+<-]");
+    assert!(program.iter().any(|instr| matches!(instr, FlatInstr::MultiplyMove(_))));
+    assert!(program.iter().any(|instr| matches!(instr, FlatInstr::ScanCells(_))));
+    assert_eq!(decode(&encode(&program)), Ok(program));
+}
+
+#[test]
+fn damaged_bytecode_is_rejected() {
+    let bytes = encode(&compiled("+[>+<-]"));
+    assert_eq!(decode(b"+[>+<-]"), Err(DecodeError::NotBytecode));
+    assert_eq!(decode(&bytes[..bytes.len() - 1]), Err(DecodeError::Truncated));
+}
+
+#[test]
+fn broken_jumps_and_empty_ranges_are_rejected() {
+    use FlatInstr::*;
+    // instructions start at byte 8, a jump or an `Add` takes 5 bytes
+    let broken = [
+        (vec![JumpIfZero(9), Add(1)], DecodeError::BadJump(8)),
+        (vec![Add(1), JumpIfNonZero(0)], DecodeError::BadJump(13)),
+        (vec![JumpIfZero(3), Add(1), JumpIfNonZero(0)], DecodeError::BadJump(18)),
+        // the inner loop would end after the outer one
+        (vec![JumpIfZero(3), JumpIfZero(5), JumpIfNonZero(1), Add(1), JumpIfNonZero(2)], DecodeError::BadJump(13)),
+        (vec![Add(1), ClearRange(0)], DecodeError::EmptyClearRange(13)),
+    ];
+    for (program, error) in broken {
+        assert_eq!(decode(&encode(&program)), Err(error));
+    }
+    assert_eq!(DecodeError::BadJump(8).to_string(), "jump at byte 8 doesn't match up with its loop");
+
+    // an `If` only has its `JumpIfZero`, and can end with the program
+    let program = compiled("+[>+<[-]]>[[-]<[>+<-]]");
+    assert_eq!(decode(&encode(&program)), Ok(program));
+}
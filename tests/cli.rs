@@ -0,0 +1,517 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/// writes `source` to a fresh file in the temp dir and returns its path
+fn program_file(name: &str, source: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("brainfuckers-{}-{}.bf", std::process::id(), name));
+    std::fs::write(&path, source).unwrap();
+    path
+}
+
+fn brainfuckers(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_brainfuckers")).args(args).output().unwrap()
+}
+
+#[test]
+fn debug_mode_shows_opcodes_before_and_after_optimizing() {
+    let path = program_file("debug-opcodes", "+++");
+    let output = brainfuckers(&["run", path.to_str().unwrap(), "-vv"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+
+    let original = lines.iter().position(|line| *line == "original Opcodes:").unwrap();
+    let optimized = lines.iter().position(|line| *line == "optimized Opcodes:").unwrap();
+    assert_eq!(lines[original + 1], "[Increment, Increment, Increment]");
+    assert_eq!(lines[optimized + 1], "[SetCell(3)]");
+}
+
+#[test]
+fn too_much_verbosity_lists_the_levels() {
+    let path = program_file("verbosity", "+");
+    let output = brainfuckers(&[path.to_str().unwrap(), "-vvvv"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("unknown verbosity '-vvvv', the levels are:\n  -v    print how long"));
+    assert!(stderr.contains("\n  -vvv  also print every change the optimizer made\n"));
+}
+
+#[test]
+fn dump_prints_bytecode_instead_of_running() {
+    let path = program_file("dump", "++[>+.<-]");
+    let output = brainfuckers(&["dump", path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\
+0000 SetCell(2)
+0001 JumpIfZero(8)
+0002 AddAt(1, 1)
+0003 Move(1)
+0004 Write
+0005 AddAt(-1, 4294967295)
+0006 Move(-1)
+0007 JumpIfNonZero(2)
+");
+}
+
+#[test]
+fn disasm_lists_jump_targets_and_where_instructions_came_from() {
+    let path = program_file("disasm", "++[>-.<-]\n,[.,]");
+    let output = brainfuckers(&["disasm", path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\
+0000 SetCell 2           ; line 1, column 1
+0001 JumpIfZero 0008     ; line 1, column 3
+0002 AddAt +1 -1         ; line 1, column 5
+0003 Move +1             ; line 1, column 4
+0004 Write               ; line 1, column 6
+0005 AddAt -1 -1         ; line 1, column 8
+0006 Move -1             ; line 1, column 7
+0007 JumpIfNonZero 0002  ; line 1, column 9
+0008 Read                ; line 2, column 1
+0009 JumpIfZero 0013     ; line 2, column 2
+0010 Write               ; line 2, column 3
+0011 Read                ; line 2, column 4
+0012 JumpIfNonZero 0010  ; line 2, column 5
+");
+}
+
+#[test]
+fn bad_arguments_print_usage() {
+    let output = brainfuckers(&["run", "--eof", "sometimes", "prog.bf"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("usage: brainfuckers"));
+}
+
+#[test]
+fn compiled_programs_run_like_their_source() {
+    let source = program_file("compile", "++++++++[>++++[>++>+++<<-]>+<<-]>>.>+.");
+    let compiled = std::env::temp_dir().join(format!("brainfuckers-{}-compile.bfc", std::process::id()));
+    let output = brainfuckers(&["compile", source.to_str().unwrap(), "-o", compiled.to_str().unwrap()]);
+    assert!(output.status.success());
+
+    let direct = brainfuckers(&[source.to_str().unwrap()]);
+    let loaded = brainfuckers(&["run", compiled.to_str().unwrap()]);
+    assert!(loaded.status.success());
+    assert_eq!(loaded.stdout, direct.stdout);
+    assert_eq!(loaded.stdout, b"Ha");
+}
+
+#[test]
+fn compiled_programs_cant_run_on_a_clamped_tape() {
+    let source = program_file("compile-clamp", "<+>.<.");
+    let compiled = std::env::temp_dir().join(format!("brainfuckers-{}-compile-clamp.bfc", std::process::id()));
+    assert!(brainfuckers(&["compile", source.to_str().unwrap(), "-o", compiled.to_str().unwrap()]).status.success());
+
+    let direct = brainfuckers(&[source.to_str().unwrap(), "--tape-mode", "clamp", "--numeric-output"]);
+    assert_eq!(direct.stdout, b"0 1 ");
+    let loaded = brainfuckers(&[compiled.to_str().unwrap(), "--tape-mode", "clamp", "--numeric-output"]);
+    assert_eq!(loaded.status.code(), Some(1));
+    assert!(loaded.stdout.is_empty());
+    assert!(String::from_utf8(loaded.stderr).unwrap().starts_with("compiled programs are optimized for a tape that wraps,"));
+}
+
+#[test]
+fn jit_needs_its_feature_and_a_plain_tape() {
+    let source = program_file("jit", "++++++++[>++++[>++>+++<<-]>+<<-]>>.>+.,.");
+    let output = Command::new(env!("CARGO_BIN_EXE_brainfuckers")).args(["run", "--jit", "--eof", "zero", source.to_str().unwrap()]).stdin(Stdio::null()).output().unwrap();
+    if cfg!(feature = "jit") {
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"Ha\0");
+    } else {
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8(output.stderr).unwrap().starts_with("--jit needs the crate built with the jit feature"));
+    }
+
+    let limited = brainfuckers(&["run", "--jit", "--max-steps", "100", source.to_str().unwrap()]);
+    assert_eq!(limited.status.code(), Some(2));
+}
+
+#[test]
+fn stopping_tapes_see_moves_that_come_back() {
+    let program = program_file("error-excursion", "<>+.");
+    for level in ["-O0", "-O2"] {
+        let output = brainfuckers(&[program.to_str().unwrap(), "--tape-mode", "error", level]);
+        assert_eq!(output.status.code(), Some(4), "{}", level);
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8(output.stderr).unwrap().starts_with("error running program: instruction 0000 moved the data pointer out of bounds to cell #-1"));
+    }
+
+    let compiled = std::env::temp_dir().join(format!("brainfuckers-{}-error-excursion.bfc", std::process::id()));
+    assert!(brainfuckers(&["compile", program.to_str().unwrap(), "-o", compiled.to_str().unwrap()]).status.success());
+    assert_eq!(brainfuckers(&[compiled.to_str().unwrap(), "--strict-bounds"]).status.code(), Some(1));
+}
+
+#[test]
+fn passes_can_be_turned_off() {
+    let path = program_file("no-opt", "++>");
+    let output = brainfuckers(&["dump", path.to_str().unwrap(), "--no-opt"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0000 Add(1)\n0001 Add(1)\n0002 Move(1)\n");
+
+    let output = brainfuckers(&["dump", path.to_str().unwrap(), "--passes", "run-length"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0000 Add(2)\n0001 Move(1)\n");
+
+    let output = brainfuckers(&["dump", path.to_str().unwrap(), "--passes", "run-length,bogus"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn output_flag_writes_program_output_to_a_file() {
+    let path = program_file("output", "-.-.");
+    let written = std::env::temp_dir().join(format!("brainfuckers-{}-output.bin", std::process::id()));
+    let output = brainfuckers(&[path.to_str().unwrap(), "--output", written.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(std::fs::read(&written).unwrap(), [255, 254]);
+}
+
+#[test]
+fn high_bytes_are_written_raw() {
+    // 200 would be two bytes as utf-8
+    let path = program_file("raw-byte", "++++++++++[>++++++++++++++++++++<-]>.");
+    let output = brainfuckers(&[path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [200]);
+}
+
+#[test]
+fn bang_input_reads_from_the_end_of_the_source() {
+    let path = program_file("bang-input", ",[.,] echo!hi there!");
+    let output = brainfuckers(&[path.to_str().unwrap(), "--bang-input", "--eof", "zero"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"hi there!");
+}
+
+#[test]
+fn programs_without_symbols_run_with_a_warning() {
+    for (name, source) in [("empty", ""), ("comments", "just some words\n")] {
+        let path = program_file(name, source);
+        let output = brainfuckers(&[path.to_str().unwrap()]);
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8(output.stderr).unwrap().starts_with("warning: no brainfuck symbols found"));
+    }
+}
+
+#[test]
+fn resuming_a_checkpoint_carries_on_where_it_stopped() {
+    let path = program_file("checkpoint", ",[.,]++++++++[>++++++++<-]>+.");
+    let input = program_file("checkpoint-input", "abc");
+    let checkpoint = path.with_extension("bfs");
+    let run = |extra: &[&str]| {
+        let mut args = vec![path.to_str().unwrap(), "--input", input.to_str().unwrap(), "--eof", "zero"];
+        args.extend_from_slice(extra);
+        brainfuckers(&args).stdout
+    };
+
+    let stopped = run(&["--checkpoint-every", "3", "--max-steps", "4"]);
+    let resumed = run(&["--resume", checkpoint.to_str().unwrap()]);
+    assert_eq!([stopped, resumed].concat(), run(&[]));
+    assert_eq!(run(&[]), b"abcA");
+}
+
+#[test]
+fn checkpoints_keep_the_output_written_so_far() {
+    // with -o the output isn't flushed before a read, so only the checkpoint can
+    let path = program_file("checkpoint-killed", "++++++++[>++++++++<-]>+.,.");
+    let checkpoint = path.with_extension("bfs");
+    let written = std::env::temp_dir().join(format!("brainfuckers-{}-checkpoint-killed.out", std::process::id()));
+    let run = |extra: &[&str], input: Option<&[u8]>| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_brainfuckers")).arg(&path).arg("-o").arg(&written).args(extra).stdin(Stdio::piped()).spawn().unwrap();
+        match input {
+            Some(input) => {
+                child.stdin.take().unwrap().write_all(input).unwrap();
+                child.wait().unwrap();
+            }
+            // dies while it waits for input, after the checkpoint of the `.` before it
+            None => {
+                let started = std::time::Instant::now();
+                while std::fs::metadata(&written).map_or(true, |file| file.len() == 0) && started.elapsed().as_secs() < 10 {
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                child.kill().unwrap();
+                child.wait().unwrap();
+            }
+        }
+        std::fs::read(&written).unwrap()
+    };
+
+    assert_eq!(run(&["--checkpoint-every", "1"], None), b"A");
+    let resumed = run(&["--resume", checkpoint.to_str().unwrap()], Some(b"z"));
+    assert_eq!(resumed, run(&[], Some(b"z")));
+    assert_eq!(resumed, b"Az");
+}
+
+#[test]
+fn sources_dont_have_to_be_utf8() {
+    let path = std::env::temp_dir().join(format!("brainfuckers-{}-latin1.bf", std::process::id()));
+    // "caf\xe9" is latin-1, and broken utf-8
+    std::fs::write(&path, b"caf\xe9 \xff\xfe ++++++++[>++++++++<-]>+.").unwrap();
+    let output = brainfuckers(&[path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"A");
+}
+
+#[test]
+fn quiet_only_prints_the_program_output() {
+    let path = program_file("quiet", "");
+    let output = brainfuckers(&[path.to_str().unwrap(), "-v", "--quiet"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+
+    let path = program_file("time-stderr", "++++++++[>++++++++<-]>+.");
+    let output = brainfuckers(&[path.to_str().unwrap(), "--time-stderr"]);
+    assert_eq!(output.stdout, b"A");
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("execution took: "));
+}
+
+#[test]
+fn init_tape_loads_the_first_cells() {
+    let data = std::env::temp_dir().join(format!("brainfuckers-{}-init-tape.bin", std::process::id()));
+    std::fs::write(&data, "HAL").unwrap();
+    let program = program_file("init-tape", "+.>+.>+.");
+    let output = brainfuckers(&[program.to_str().unwrap(), "--init-tape", data.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"IBM");
+
+    let output = brainfuckers(&[program.to_str().unwrap(), "--init-tape", data.to_str().unwrap(), "--tape-size", "2"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("the initial tape has 3 bytes, but the tape only has 2 cells\n"));
+}
+
+#[test]
+fn lenient_runs_programs_with_unmatched_brackets() {
+    let stray = program_file("lenient-stray", "++++++++[>++++++++<-]>+.]");
+    let output = brainfuckers(&[stray.to_str().unwrap(), "--lenient"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"A");
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("warning: dropped or closed 1 unmatched brackets\n"));
+
+    let unclosed = program_file("lenient-unclosed", "+[.-");
+    let output = brainfuckers(&[unclosed.to_str().unwrap(), "--lenient", "-q"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [1]);
+    assert!(output.stderr.is_empty());
+
+    assert!(!brainfuckers(&[unclosed.to_str().unwrap()]).status.success());
+}
+
+#[test]
+fn strict_bounds_errors_point_at_the_source() {
+    let program = program_file("strict-bounds", "+.\n<.");
+    let output = brainfuckers(&[program.to_str().unwrap(), "--strict-bounds"]);
+    assert!(!output.status.success());
+    assert_eq!(output.stdout, [1]);
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "error running program: instruction 0002 moved the data pointer out of bounds to cell #-1, at line 2, column 1\n");
+}
+
+#[test]
+fn fmt_rewrites_the_file_in_place() {
+    let program = program_file("fmt", "+[ loop [-] ]");
+    let output = brainfuckers(&["fmt", program.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"+[ loop\n  [-]\n]\n");
+
+    assert!(brainfuckers(&["fmt", program.to_str().unwrap(), "-i", "--strip-comments"]).status.success());
+    assert_eq!(std::fs::read_to_string(&program).unwrap(), "+[\n  [-]\n]\n");
+}
+
+#[test]
+fn saturating_cells_keep_plus_and_minus_apart() {
+    // merged into `SetCell(1)` this would print 1 instead of 2
+    let program = program_file("saturate", "-++.");
+    let output = brainfuckers(&[program.to_str().unwrap(), "--cell-overflow", "saturate"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [2]);
+}
+
+#[test]
+fn trace_prints_every_instruction_to_stderr() {
+    let program = program_file("trace", "+>++.<.");
+    let output = brainfuckers(&[program.to_str().unwrap(), "--trace", "--no-opt"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [2, 1]);
+    let trace = String::from_utf8(output.stderr).unwrap();
+    assert!(trace.starts_with("0000 Add(1) at line 1, column 1: cell #0 = 0\n0001 Move(1) at line 1, column 2: cell #0 = 1\n"));
+    assert_eq!(trace.lines().count(), 7);
+
+    let output = brainfuckers(&[program.to_str().unwrap(), "--trace-range", "1:5", "--no-opt"]);
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "\
+0002 Add(1) at line 1, column 3: cell #1 = 0
+0003 Add(1) at line 1, column 4: cell #1 = 1
+0004 Write at line 1, column 5: cell #1 = 2
+0005 Move(-1) at line 1, column 6: cell #1 = 2
+");
+}
+
+#[test]
+fn compat_classic_sets_the_original_semantics() {
+    // 0 - 1 wraps to 255 and back, and `,` at the end of input leaves the 0 alone
+    let program = program_file("compat", "-.+,.<");
+    let output = brainfuckers(&[program.to_str().unwrap(), "--cell-size", "16", "--eof", "neg1", "--compat", "classic"]);
+    assert_eq!(output.stdout, [255, 0]);
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("error running program: instruction 0005 moved the data pointer out of bounds to cell #-1"));
+
+    // options after it win
+    let output = brainfuckers(&[program.to_str().unwrap(), "--compat", "classic", "--eof", "zero", "--tape-mode", "wrap"]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn programs_that_never_print_get_a_warning() {
+    let program = program_file("no-write", "+++[>+<-]");
+    let output = brainfuckers(&[program.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("warning: the program has no `.`, so it never prints anything\n"));
+    assert!(brainfuckers(&[program.to_str().unwrap(), "-q"]).stderr.is_empty());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_brainfuckers")).arg("-").stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::piped()).spawn().and_then(|mut child| {
+        child.stdin.take().unwrap().write_all(b",.")?;
+        child.wait_with_output()
+    }).unwrap();
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("warning: the program reads with `,`, but stdin is taken"));
+}
+
+#[test]
+fn output_nobody_reads_is_an_error_and_not_a_panic() {
+    let program = program_file("broken-pipe", "+[.]");
+    let output = Command::new(env!("CARGO_BIN_EXE_brainfuckers")).arg(&program).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().and_then(|mut child| {
+        drop(child.stdout.take());
+        child.wait_with_output()
+    }).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("error writing output: "), "{}", stderr);
+    assert!(!stderr.contains("panicked"));
+}
+
+#[test]
+fn debugger_breakpoints_take_a_spot_in_the_source() {
+    let program = program_file("debug-position", "+++\n[>++.<-]");
+    let output = Command::new(env!("CARGO_BIN_EXE_brainfuckers")).arg(&program).arg("--debug-step").stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().and_then(|mut child| {
+        child.stdin.take().unwrap().write_all(b"b 2:3\nb 1:2\nc\nq\n")?;
+        child.wait_with_output()
+    }).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("breakpoint set at 0002\n"), "{}", stderr);
+    assert!(stderr.contains("no instruction at line 1, column 2\n"));
+    assert!(stderr.contains("\n> 0002 AddAt(1, 2) at line 2, column 3\n"));
+}
+
+#[test]
+fn optimize_levels_dont_change_the_output() {
+    let input = std::env::temp_dir().join(format!("brainfuckers-{}-levels.txt", std::process::id()));
+    std::fs::write(&input, "Hello, World! xyz").unwrap();
+    let squares = program_file("levels-squares", "++++[>+++++<-]>[<+++++>-]+<+[>[>+>+<<-]++>>[<<+>>-]>>>[-]++>[-]+>>>+[[-]++++++>>>]<<<[[<++++++++<++>>-]+<.<[>----<-]<]<<[>>>>>[>>>[-]+++++++++<[>-<-]+++++++++>[-[<->-]+[<<<]]<[>+<-]>]<<-]<<-]");
+    let programs = ["benches/programs/hello.bf", "benches/programs/rot13.bf"].map(PathBuf::from).into_iter().chain([squares]);
+    for program in programs {
+        let outputs: Vec<Vec<u8>> = ["-O0", "-O1", "-O2"].iter()
+            .map(|level| brainfuckers(&[program.to_str().unwrap(), level, "--input", input.to_str().unwrap(), "-q"]).stdout)
+            .collect();
+        assert!(!outputs[0].is_empty());
+        assert_eq!(outputs[0], outputs[1], "{:?}", program);
+        assert_eq!(outputs[0], outputs[2], "{:?}", program);
+    }
+}
+
+#[test]
+fn check_only_parses_the_program() {
+    let good = program_file("check-good", "+[.,]");
+    let output = brainfuckers(&["check", good.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+
+    let bad = program_file("check-bad", "+[.,");
+    let output = brainfuckers(&["check", bad.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("error parsing program: stray opening bracket at line 1, column 2\n"));
+}
+
+#[test]
+fn exit_codes_say_what_went_wrong() {
+    let code = |source: &str, flags: &[&str]| {
+        let path = program_file("exit-code", source);
+        let mut args = vec![path.to_str().unwrap()];
+        args.extend_from_slice(flags);
+        brainfuckers(&args).status.code()
+    };
+    assert_eq!(code("+.", &[]), Some(0));
+    assert_eq!(code("+[", &[]), Some(3));
+    assert_eq!(code("<", &["--strict-bounds"]), Some(4));
+    assert_eq!(code("+[]", &[]), Some(4));
+    assert_eq!(code("++++++[>+++++++<-]>", &["--exit-from-cell0"]), Some(0));
+    assert_eq!(code(">++++++[<+++++++>-]<.", &["--exit-from-cell0"]), Some(42));
+    assert_eq!(code("-.", &["--exit-from-cell0", "--cell-size", "16"]), Some(255));
+}
+
+#[test]
+fn strict_eof_says_how_much_input_there_was() {
+    let path = program_file("strict-eof", ",.,\n.,.");
+    let input = program_file("strict-eof-input", "ab");
+    let output = brainfuckers(&[path.to_str().unwrap(), "--input", input.to_str().unwrap(), "--eof", "error"]);
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(output.stdout, b"ab");
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "error running program: program read 3 bytes but only 2 were provided, at line 2, column 2\n");
+}
+
+#[test]
+fn output_files_get_everything_however_small_the_buffer() {
+    let path = program_file("output-buffer", ",[.,]");
+    let input = program_file("output-buffer-input", "some text to copy");
+    let written = path.with_extension("out");
+    for buffer in ["1", "3", "1048576"] {
+        let output = brainfuckers(&[path.to_str().unwrap(), "--input", input.to_str().unwrap(), "--eof", "zero", "-o", written.to_str().unwrap(), "--output-buffer", buffer]);
+        assert!(output.status.success());
+        assert_eq!(std::fs::read(&written).unwrap(), b"some text to copy");
+    }
+
+    let output = brainfuckers(&[path.to_str().unwrap(), "--output-buffer", "0"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn several_files_run_as_one_program() {
+    let header = program_file("several-header", "++++++++[>++++++++");
+    let footer = program_file("several-footer", "<-]>+.");
+    let output = brainfuckers(&[header.to_str().unwrap(), footer.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"A");
+
+    // each file's brackets are only balanced with the other's
+    for path in [&header, &footer] {
+        assert_eq!(brainfuckers(&["check", path.to_str().unwrap()]).status.code(), Some(3));
+    }
+    let output = brainfuckers(&[footer.to_str().unwrap(), header.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8(output.stderr).unwrap().ends_with(&format!("\nfile path: {}, {}\n", footer.display(), header.display())));
+}
+
+#[test]
+fn selftest_runs_the_built_in_programs() {
+    for flags in [&[][..], &["-O0"], &["--cell-size", "32", "--dynamic-tape"]] {
+        let output = brainfuckers(&[&["selftest"][..], flags].concat());
+        assert!(output.status.success());
+        assert!(String::from_utf8(output.stdout).unwrap().ends_with("\nrot13    ok\n4/4 passed\n"));
+    }
+
+    // too small a tape for the quine
+    let output = brainfuckers(&["selftest", "--tape-size", "10"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\nquine    FAILED: ") && stdout.ends_with("\n3/4 passed\n"));
+}
+
+#[test]
+fn maps_alias_other_characters() {
+    let map = program_file("map", "a +\nprint .\n");
+    let path = program_file("mapped", "aaa print");
+    let output = brainfuckers(&[path.to_str().unwrap(), "--map", map.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [3]);
+
+    let conflicting = program_file("map-conflict", "a +\na -\n");
+    let output = brainfuckers(&[path.to_str().unwrap(), "--map", conflicting.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("error reading map: `a` is mapped more than once, at line 2\n"));
+}
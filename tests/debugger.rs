@@ -0,0 +1,82 @@
+use brainfuckers::debugger::Debugger;
+use brainfuckers::{flatten, optimize_opcodes, parse, tokenize, tokenize_with_positions, FlatInstr, Options, RuntimeError};
+
+fn compiled(source: &str) -> Vec<FlatInstr> {
+    let mut opcodes = tokenize(source);
+    optimize_opcodes(&mut opcodes);
+    flatten(&parse(opcodes).unwrap())
+}
+
+/// runs `source` under the debugger with `commands` and returns its log and the tape
+fn debug_session(source: &str, commands: &str) -> (String, [u8; 16]) {
+    let mut tape = [0u8; 16];
+    let mut log = Vec::new();
+    let mut output = Vec::new();
+    Debugger::new(commands.as_bytes(), &mut log)
+        .run(&compiled(source), &mut tape, &mut 0, &mut std::io::empty(), &mut output, &Options::default())
+        .unwrap();
+    (String::from_utf8(log).unwrap(), tape)
+}
+
+#[test]
+fn stepping_stops_before_every_instruction() {
+    let (log, tape) = debug_session("++>+++", "s\ns\np\nq\n");
+    assert!(log.contains("0000 SetCell(2)\n> 0001 AddAt(1, 3)\n> 0002 Move(1)\n"));
+    assert!(log.contains("data pointer: 0\ncell  0 1 2 3 4 5 6 7 8\nvalue 2 3 0 0 0 0 0 0 0\n      ^\n"));
+    // quitting leaves the last instruction unexecuted
+    assert_eq!(tape[..2], [2, 3]);
+}
+
+#[test]
+fn continue_runs_to_the_next_breakpoint() {
+    // the loop body starts with 0002 AddAt(1, 2)
+    let (log, tape) = debug_session("+++[>++.<-]>+", "b 2\nc\nc\nc\nc\n");
+    assert_eq!(log.matches("\n> 0002 AddAt(1, 2)").count(), 3);
+    assert!(log.ends_with("program finished\n"));
+    assert_eq!(tape[1], 7);
+}
+
+#[test]
+fn source_map_shows_where_instructions_came_from() {
+    let mut tokens = tokenize_with_positions("++\n  >.");
+    optimize_opcodes(&mut tokens);
+    let (opcodes, source_map): (Vec<_>, Vec<_>) = tokens.into_iter().unzip();
+    let program = flatten(&parse(opcodes).unwrap());
+
+    let mut log = Vec::new();
+    Debugger::new(&b"s\ns\n"[..], &mut log).with_source_map(source_map)
+        .run(&program, &mut [0u8; 4], &mut 0, &mut std::io::empty(), &mut Vec::new(), &Options::default())
+        .unwrap();
+    assert!(String::from_utf8(log).unwrap().contains("0000 SetCell(2) at line 1, column 1\n> 0001 Move(1) at line 2, column 3\n"));
+}
+
+#[test]
+fn breakpoints_can_go_on_a_spot_in_the_source() {
+    let source = "+++\n[>++.<-]>+";
+    let mut tokens = tokenize_with_positions(source);
+    optimize_opcodes(&mut tokens);
+    let (opcodes, source_map): (Vec<_>, Vec<_>) = tokens.into_iter().unzip();
+    let program = flatten(&parse(opcodes).unwrap());
+
+    // the `++` at line 2, column 3 is 0002 AddAt(1, 2), so nothing is left at column 4
+    let mut log = Vec::new();
+    let mut tape = [0u8; 4];
+    Debugger::new(&b"b 2:4\nb 9:x\nb 2:3\nc\nc\nc\nc\n"[..], &mut log).with_source_map(source_map)
+        .run(&program, &mut tape, &mut 0, &mut std::io::empty(), &mut Vec::new(), &Options::default())
+        .unwrap();
+    let log = String::from_utf8(log).unwrap();
+    assert!(log.contains("no instruction at line 2, column 4\n"));
+    assert!(log.contains("'9:x' isn't a line and column\n"));
+    assert!(log.contains("breakpoint set at 0002\n"));
+    assert_eq!(log.matches("\n> 0002 AddAt(1, 2) at line 2, column 3").count(), 3);
+    assert!(log.ends_with("program finished\n"));
+    assert_eq!(tape[1], 7);
+}
+
+#[test]
+fn the_step_limit_stops_a_debugged_program() {
+    let mut log = Vec::new();
+    let result = Debugger::new(&b"s\nc\n"[..], &mut log)
+        .run(&compiled("+[>+<]"), &mut [0u8; 4], &mut 0, &mut std::io::empty(), &mut Vec::new(), &Options { max_steps: Some(10), ..Options::default() });
+    assert!(matches!(result, Err(RuntimeError::StepLimitReached(10, _))));
+}
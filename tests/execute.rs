@@ -0,0 +1,499 @@
+use std::ops::ControlFlow;
+
+use brainfuckers::{dump_tape, execute, execute_growing, execute_with_hook, flatten, optimize_opcodes, parse, tokenize, CellOverflow, EofBehavior, FlatInstr, Options, RuntimeError, Stats, TapeMode};
+
+fn run_with_input(source: &str, input: &[u8], eof: EofBehavior) -> Vec<u8> {
+    let mut opcodes = tokenize(source);
+    optimize_opcodes(&mut opcodes);
+    let program = flatten(&parse(opcodes).unwrap());
+
+    let mut tape = [0u8; 16];
+    let mut output = Vec::new();
+    execute(&program, &mut tape, &mut 0, &mut &input[..], &mut output, &Options { eof, ..Options::default() }, None).unwrap();
+    output
+}
+
+fn scan(tape: &mut [u8], start: usize, right: bool) -> Result<usize, RuntimeError> {
+    let mut data_pointer = start;
+    let mut output = Vec::new();
+    execute(&[FlatInstr::ScanCells(right)], tape, &mut data_pointer, &mut std::io::empty(), &mut output, &Options::default(), None)?;
+    Ok(data_pointer)
+}
+
+#[test]
+fn scans_wrap_around_the_tape() {
+    assert_eq!(scan(&mut [0, 1, 1, 0, 1], 1, true), Ok(3));
+    assert_eq!(scan(&mut [0, 1, 1, 1, 1], 2, true), Ok(0));
+    assert_eq!(scan(&mut [1, 1, 0, 1, 1], 1, false), Ok(2));
+    assert_eq!(scan(&mut [1, 1, 1, 0, 1], 1, false), Ok(3));
+    assert_eq!(scan(&mut [1, 0, 1, 1, 1], 1, false), Ok(1));
+}
+
+#[test]
+fn scanning_left_from_the_first_cell_carries_on_at_the_last() {
+    assert_eq!(scan(&mut [1, 1, 1, 0, 1], 0, false), Ok(3));
+
+    // the same as moving left one cell at a time would do
+    let mut tape = vec![1u8; 30000];
+    tape[29990] = 0;
+    let program = flatten(&parse(tokenize("[<]")).unwrap());
+    let mut opcodes = tokenize("[<]");
+    optimize_opcodes(&mut opcodes);
+    let optimized = flatten(&parse(opcodes).unwrap());
+    assert_eq!(optimized, [FlatInstr::ScanCells(false)]);
+    for program in [program, optimized] {
+        let mut data_pointer = 0;
+        execute(&program, &mut tape.clone(), &mut data_pointer, &mut std::io::empty(), &mut Vec::new(), &Options::default(), None).unwrap();
+        assert_eq!(data_pointer, 29990);
+    }
+}
+
+#[test]
+fn big_moves_wrap_around_the_tape() {
+    let run_moves = |moves: &[FlatInstr], tape_size: usize| {
+        let mut data_pointer = 0;
+        execute(moves, &mut vec![0u8; tape_size], &mut data_pointer, &mut std::io::empty(), &mut Vec::new(), &Options::default(), None).unwrap();
+        data_pointer
+    };
+    assert_eq!(run_moves(&[FlatInstr::Move(-40000)], 30000), 20000);
+    assert_eq!(run_moves(&[FlatInstr::Move(5), FlatInstr::Move(-40000)], 30000), 20005);
+    assert_eq!(run_moves(&[FlatInstr::Move(-7)], 3), 2);
+    // one cell to the left in total
+    assert_eq!(run_moves(&[FlatInstr::Move(i32::MAX), FlatInstr::Move(i32::MIN)], 30000), 29999);
+}
+
+#[test]
+fn clear_ranges_follow_the_tape_mode() {
+    let clear = |data_pointer: usize, tape_mode| {
+        let mut tape = [1u8; 5];
+        let mut data_pointer = data_pointer;
+        let result = execute(&[FlatInstr::ClearRange(3)], &mut tape, &mut data_pointer, &mut std::io::empty(), &mut Vec::new(), &Options { tape_mode, ..Options::default() }, None);
+        (result, tape, data_pointer)
+    };
+    assert_eq!(clear(1, TapeMode::Wrap), (Ok(()), [1, 0, 0, 0, 1], 3));
+    assert_eq!(clear(3, TapeMode::Wrap), (Ok(()), [0, 1, 1, 0, 0], 0));
+    assert_eq!(clear(3, TapeMode::Clamp), (Ok(()), [1, 1, 1, 0, 0], 4));
+    // nothing is cleared if the range doesn't fit
+    assert_eq!(clear(3, TapeMode::Error), (Err(RuntimeError::PointerOutOfBounds(5, 0)), [1; 5], 3));
+
+    let mut tape = [1u8; 3];
+    execute(&[FlatInstr::Move(1), FlatInstr::ClearRange(7)], &mut tape, &mut 0, &mut std::io::empty(), &mut Vec::new(), &Options::default(), None).unwrap();
+    assert_eq!(tape, [0; 3]);
+}
+
+#[test]
+fn scan_without_zero_cell_is_an_error() {
+    assert_eq!(scan(&mut [1, 1, 1], 0, true), Err(RuntimeError::NoZeroCell));
+    assert_eq!(scan(&mut [1, 1, 1], 2, false), Err(RuntimeError::NoZeroCell));
+}
+
+#[test]
+fn stats_count_through_nested_loops() {
+    let mut opcodes = tokenize("++[>+++[-.]<-]");
+    optimize_opcodes(&mut opcodes);
+    let program = flatten(&parse(opcodes).unwrap());
+
+    let mut tape = [0u8; 4];
+    let mut data_pointer = 0;
+    let mut output = Vec::new();
+    let mut stats = Stats::default();
+    execute(&program, &mut tape, &mut data_pointer, &mut std::io::empty(), &mut output, &Options::default(), Some(&mut stats)).unwrap();
+
+    // 2 outer iterations, each running the inner loop 3 times
+    assert_eq!(stats.count("Write"), 6);
+    assert_eq!(stats.count("JumpIfZero"), 1 + 2);
+    assert_eq!(stats.count("JumpIfNonZero"), 2 + 2 * 3);
+    assert_eq!(stats.count("SetCell"), 1);
+    // the outer body folds into AddAt, Move, <inner loop>, AddAt, Move
+    assert_eq!(stats.count("AddAt"), 4);
+    assert_eq!(stats.total(), 1 + 3 + 2 * (4 + 4 + 6));
+}
+
+#[test]
+fn stats_count_loop_iterations() {
+    let program = flatten(&parse(tokenize("++[>+++[.-]<-]")).unwrap());
+    let mut stats = Stats::default();
+    execute(&program, &mut [0u8; 4], &mut 0, &mut std::io::empty(), &mut Vec::new(), &Options::default(), Some(&mut stats)).unwrap();
+
+    // the outer loop starts at 2, the inner one at 7
+    assert_eq!(stats.loop_iterations(2), 2);
+    assert_eq!(stats.loop_iterations(7), 2 * 3);
+    assert_eq!(stats.hottest_loops(10), [(7, 6), (2, 2)]);
+    assert_eq!(stats.hottest_loops(1), [(7, 6)]);
+}
+
+#[test]
+fn stats_count_iterations_per_loop_entry() {
+    let program = flatten(&parse(tokenize("++[>[-]+++<-]>>[-]")).unwrap());
+    let mut stats = Stats::default();
+    execute(&program, &mut [0u8; 4], &mut 0, &mut std::io::empty(), &mut Vec::new(), &Options::default(), Some(&mut stats)).unwrap();
+
+    // the inner loop is entered once on a zero cell and once on a 3
+    assert_eq!(stats.loop_histogram(4), [(0, 1), (3, 1)]);
+    assert_eq!(stats.loop_histogram(2), [(2, 1)]);
+    assert_eq!(stats.loop_histogram(15), [(0, 1)]);
+    assert_eq!(stats.loop_histogram(3), []);
+}
+
+#[test]
+fn stats_track_the_highest_cell_used() {
+    let highest = |source: &str| {
+        let mut opcodes = tokenize(source);
+        optimize_opcodes(&mut opcodes);
+        let program = flatten(&parse(opcodes).unwrap());
+        let mut stats = Stats::default();
+        execute(&program, &mut [0u8; 16], &mut 0, &mut std::io::empty(), &mut Vec::new(), &Options::default(), Some(&mut stats)).unwrap();
+        stats.highest_cell()
+    };
+    assert_eq!(highest(">>>+<<"), 3);
+    // folded into an offset, so the pointer itself never gets there
+    assert_eq!(highest(">>>>>+<<<<<"), 5);
+    assert_eq!(highest("++[->>>>+<<<<]"), 4);
+    assert_eq!(highest("+>+>+>[<]"), 3);
+    // wrapping around reaches the end of the tape
+    assert_eq!(highest("<+>"), 15);
+}
+
+#[test]
+fn reads_come_from_the_given_input() {
+    assert_eq!(run_with_input(",[.,]", b"cat", EofBehavior::Zero), b"cat");
+    assert_eq!(run_with_input(",+.,+.", b"ab", EofBehavior::Unchanged), b"bc");
+}
+
+#[test]
+fn eof_behavior_applies_once_input_runs_out() {
+    assert_eq!(run_with_input("+++,.", b"", EofBehavior::Unchanged), vec![3]);
+    assert_eq!(run_with_input("+++,.", b"", EofBehavior::Zero), vec![0]);
+    assert_eq!(run_with_input("+++,.", b"", EofBehavior::NegOne), vec![255]);
+}
+
+#[test]
+fn reading_past_the_input_can_be_an_error() {
+    let program = flatten(&parse(tokenize(",.,.,.")).unwrap());
+    let mut output = Vec::new();
+    let options = Options { eof: EofBehavior::Error, ..Options::default() };
+    let result = execute(&program, &mut [0u8; 4], &mut 0, &mut &b"ab"[..], &mut output, &options, None);
+    assert_eq!(result, Err(RuntimeError::InputExhausted(4)));
+    assert_eq!(output, b"ab");
+}
+
+#[test]
+fn strict_bounds_reports_the_attempted_cell() {
+    let strict = Options { tape_mode: TapeMode::Error, ..Options::default() };
+    let run_strict = |program: &[FlatInstr], tape: &mut [u8]| {
+        execute(program, tape, &mut 0, &mut std::io::empty(), &mut Vec::new(), &strict, None)
+    };
+
+    assert_eq!(run_strict(&[FlatInstr::Move(-1)], &mut [0; 4]), Err(RuntimeError::PointerOutOfBounds(-1, 0)));
+    assert_eq!(run_strict(&[FlatInstr::Move(3), FlatInstr::Move(2)], &mut [0; 4]), Err(RuntimeError::PointerOutOfBounds(5, 1)));
+    assert_eq!(run_strict(&[FlatInstr::AddAt(4, 1)], &mut [0; 4]), Err(RuntimeError::PointerOutOfBounds(4, 0)));
+    assert_eq!(run_strict(&[FlatInstr::ScanCells(true)], &mut [1, 1, 1, 2]), Err(RuntimeError::PointerOutOfBounds(4, 0)));
+    assert_eq!(run_strict(&[FlatInstr::ScanCells(false)], &mut [1, 0, 1, 2]), Err(RuntimeError::PointerOutOfBounds(-1, 0)));
+    assert_eq!(run_strict(&[FlatInstr::Move(3), FlatInstr::Move(-3)], &mut [0; 4]), Ok(()));
+}
+
+#[test]
+fn clamped_pointers_stay_on_the_tape() {
+    let clamp = Options { tape_mode: TapeMode::Clamp, ..Options::default() };
+    let run_clamped = |program: &[FlatInstr], tape: &mut [u8], start: usize| {
+        let mut data_pointer = start;
+        execute(program, tape, &mut data_pointer, &mut std::io::empty(), &mut Vec::new(), &clamp, None).map(|()| data_pointer)
+    };
+
+    assert_eq!(run_clamped(&[FlatInstr::Move(-1)], &mut [0; 4], 0), Ok(0));
+    assert_eq!(run_clamped(&[FlatInstr::Move(5)], &mut [0; 4], 1), Ok(3));
+    assert_eq!(run_clamped(&[FlatInstr::Move(3), FlatInstr::Move(-1)], &mut [0; 4], 2), Ok(2));
+    let mut tape = [0; 4];
+    assert_eq!(run_clamped(&[FlatInstr::AddAt(9, 1), FlatInstr::AddAt(-9, 2)], &mut tape, 1), Ok(1));
+    assert_eq!(tape, [2, 0, 0, 1]);
+    // the loop would sit on the last cell forever
+    assert_eq!(run_clamped(&[FlatInstr::ScanCells(true)], &mut [0, 1, 1, 1], 1), Err(RuntimeError::NoZeroCell));
+    assert_eq!(run_clamped(&[FlatInstr::ScanCells(false)], &mut [0, 1, 1, 1], 2), Ok(0));
+}
+
+#[test]
+fn wrapped_pointers_come_back_at_the_other_end() {
+    let mut data_pointer = 0;
+    let program = [FlatInstr::Move(-1), FlatInstr::Add(1), FlatInstr::Move(2), FlatInstr::Add(2)];
+    let mut tape = [0u8; 4];
+    execute(&program, &mut tape, &mut data_pointer, &mut std::io::empty(), &mut Vec::new(), &Options::default(), None).unwrap();
+    assert_eq!((tape, data_pointer), ([0, 2, 0, 1], 1));
+}
+
+#[test]
+fn growing_tapes_extend_past_their_end() {
+    let run_growing = |program: &[FlatInstr], tape: &mut Vec<u8>| {
+        let mut data_pointer = 0;
+        execute_growing(program, tape, &mut data_pointer, &mut std::io::empty(), &mut Vec::new(), &Options::default(), None).map(|()| data_pointer)
+    };
+
+    let mut tape = vec![0];
+    assert_eq!(run_growing(&[FlatInstr::Move(5), FlatInstr::Add(1)], &mut tape), Ok(5));
+    assert_eq!(&tape[..6], [0, 0, 0, 0, 0, 1]);
+    // every target gets added to once, even though the last one made the tape grow
+    let mut tape = vec![3, 0];
+    assert_eq!(run_growing(&[FlatInstr::MultiplyMove(vec![(1, 1), (4, 2)])], &mut tape), Ok(0));
+    assert_eq!(&tape[..5], [0, 3, 0, 0, 6]);
+    let mut tape = vec![1, 1];
+    assert_eq!(run_growing(&[FlatInstr::ScanCells(true)], &mut tape), Ok(2));
+    assert_eq!(run_growing(&[FlatInstr::Move(2), FlatInstr::Move(-3)], &mut vec![0]), Err(RuntimeError::PointerOutOfBounds(-1, 1)));
+}
+
+#[test]
+fn tape_dump_stops_at_last_nonzero_cell() {
+    let mut tape = [0u8; 40];
+    tape[0] = 0x48;
+    tape[17] = 0xff;
+    let mut dump = Vec::new();
+    dump_tape(&tape, 3, &mut dump).unwrap();
+    assert_eq!(String::from_utf8(dump).unwrap(), concat!(
+        "data pointer: 3\n",
+        "00000000  48 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00\n",
+        "00000010  00 ff\n",
+    ));
+
+    let mut dump = Vec::new();
+    dump_tape(&[0u16, 0x1234], 0, &mut dump).unwrap();
+    assert_eq!(String::from_utf8(dump).unwrap(), "data pointer: 0\n00000000  0000 1234\n");
+}
+
+#[test]
+fn max_steps_truncates_endless_loops() {
+    let mut opcodes = tokenize("+[>+.<]");
+    optimize_opcodes(&mut opcodes);
+    let program = flatten(&parse(opcodes).unwrap());
+    let limited = Options { max_steps: Some(100), ..Options::default() };
+
+    let mut output = Vec::new();
+    let result = execute(&program, &mut [0u8; 4], &mut 0, &mut std::io::empty(), &mut output, &limited, None);
+    assert_eq!(result, Err(RuntimeError::StepLimitReached(100, 5)));
+    assert_eq!(output.len(), 20);
+
+    // a program that finishes right at the limit isn't affected
+    let exact = vec![FlatInstr::Add(1); 100];
+    assert_eq!(execute(&exact, &mut [0u8; 4], &mut 0, &mut std::io::empty(), &mut Vec::new(), &limited, None), Ok(()));
+}
+
+#[test]
+fn timeout_stops_endless_loops() {
+    let mut opcodes = tokenize("+[>+<]");
+    optimize_opcodes(&mut opcodes);
+    let program = flatten(&parse(opcodes).unwrap());
+    let timeout = std::time::Duration::from_millis(50);
+    let limited = Options { timeout: Some(timeout), ..Options::default() };
+    let result = execute(&program, &mut [0u8; 4], &mut 0, &mut std::io::empty(), &mut Vec::new(), &limited, None);
+    assert_eq!(result, Err(RuntimeError::TimedOut(timeout)));
+}
+
+#[test]
+fn interrupt_stops_endless_loops() {
+    static INTERRUPT: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(true);
+    let mut opcodes = tokenize("+[>+<]");
+    optimize_opcodes(&mut opcodes);
+    let program = flatten(&parse(opcodes).unwrap());
+    let options = Options { interrupt: Some(&INTERRUPT), ..Options::default() };
+    let result = execute(&program, &mut [0u8; 4], &mut 0, &mut std::io::empty(), &mut Vec::new(), &options, None);
+    assert_eq!(result, Err(RuntimeError::Interrupted(0, 0)));
+}
+
+#[test]
+fn hash_prints_the_cells_around_the_data_pointer() {
+    // tape of 16 cells, so the window is cut off on the right
+    let output = run_with_input(">>>>>>>>>>++++++++++>+#", b"", EofBehavior::Unchanged);
+    assert_eq!(String::from_utf8(output).unwrap(), "\
+cell   3  4  5  6  7  8  9 10 11 12 13 14 15
+value  0  0  0  0  0  0  0 10  1  0  0  0  0
+                               ^
+");
+}
+
+#[test]
+fn tape_state_prints_values_and_marks_the_data_pointer() {
+    let output = run_with_input("++>+++>>|<<<|", b"", EofBehavior::Unchanged);
+    assert_eq!(String::from_utf8(output).unwrap(), "[2 3 0 >0]\n[>2 3]\n");
+}
+
+#[test]
+fn visualize_draws_a_highlighted_grid() {
+    let program = [FlatInstr::Add(200), FlatInstr::Move(2), FlatInstr::TapeState];
+    let options = Options { visualize: true, ..Options::default() };
+    let mut output = Vec::new();
+    execute(&program, &mut [0u8; 40], &mut 0, &mut std::io::empty(), &mut output, &options, None).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(),
+        "\x1b[H\x1b[2J00000000  \x1b[38;5;226m200\x1b[0m \x1b[2m  0\x1b[0m \x1b[2m\x1b[7m  0\x1b[0m\n");
+}
+
+#[test]
+fn wide_cells_write_unicode_characters() {
+    let mut output = Vec::new();
+    execute(&[FlatInstr::Add(0x263a), FlatInstr::Write], &mut [0u16; 4], &mut 0, &mut std::io::empty(), &mut output, &Options::default(), None).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "\u{263a}");
+}
+
+#[test]
+fn utf8_input_reads_whole_characters_into_wide_cells() {
+    let read_all = |utf8_input: bool| {
+        let options = Options { utf8_input, eof: EofBehavior::Zero, ..Options::default() };
+        let mut tape = [0u16; 4];
+        let program = [FlatInstr::Read, FlatInstr::Move(1), FlatInstr::Read, FlatInstr::Move(1), FlatInstr::Read, FlatInstr::Move(1), FlatInstr::Read];
+        execute(&program, &mut tape, &mut 0, &mut &b"\xc3\xa9a\xff"[..], &mut Vec::new(), &options, None).unwrap();
+        tape
+    };
+    assert_eq!(read_all(true), [0xe9, b'a' as u16, 0xfffd, 0]);
+    assert_eq!(read_all(false), [0xc3, 0xa9, b'a' as u16, 0xff]);
+}
+
+#[test]
+fn numeric_output_prints_decimal_values() {
+    let options = Options { numeric_output: true, ..Options::default() };
+    let program = [FlatInstr::Add(42), FlatInstr::Write, FlatInstr::Sub(42), FlatInstr::Write];
+    let mut output = Vec::new();
+    execute(&program, &mut [0u8; 4], &mut 0, &mut std::io::empty(), &mut output, &options, None).unwrap();
+    assert_eq!(output, b"42 0 ");
+}
+
+#[test]
+fn ascii_table_shows_the_characters_of_cells() {
+    let options = Options { ascii_table: true, ..Options::default() };
+    let program = [FlatInstr::Add(72), FlatInstr::Move(2), FlatInstr::Add(1), FlatInstr::Move(1), FlatInstr::Add(39), FlatInstr::TapeState];
+    let mut output = Vec::new();
+    execute(&program, &mut [0u8; 8], &mut 0, &mut std::io::empty(), &mut output, &options, None).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "[72='H' 0 1='\\x01' >39='\\'']\n");
+
+    let mut output = Vec::new();
+    execute(&[FlatInstr::Add(0x263a), FlatInstr::TapeState], &mut [0u16; 2], &mut 0, &mut std::io::empty(), &mut output, &options, None).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "[>9786='\\u{263a}']\n");
+}
+
+#[test]
+fn empty_loops_on_nonzero_cells_are_reported() {
+    let run_source = |source: &str| {
+        let mut opcodes = tokenize(source);
+        optimize_opcodes(&mut opcodes);
+        let program = flatten(&parse(opcodes).unwrap());
+        execute(&program, &mut [0u8; 4], &mut 0, &mut std::io::empty(), &mut Vec::new(), &Options::default(), None)
+    };
+    assert_eq!(run_source("[]+"), Ok(()));
+    assert_eq!(run_source("+.[]"), Err(RuntimeError::InfiniteLoop(2)));
+    // the moves cancel out and leave an empty loop behind
+    assert_eq!(run_source("+[<>]"), Err(RuntimeError::InfiniteLoop(1)));
+}
+
+#[test]
+fn hooks_see_every_instruction_and_can_stop() {
+    let mut opcodes = tokenize("+++[>+<-]>.");
+    optimize_opcodes(&mut opcodes);
+    let program = flatten(&parse(opcodes).unwrap());
+
+    let mut trace = Vec::new();
+    let mut output = Vec::new();
+    execute_with_hook(&program, &mut [0u8; 4], &mut 0, &mut std::io::empty(), &mut output, &Options::default(), |at, instr, tape: &[u8], data_pointer| {
+        trace.push((at, instr.name(), tape[data_pointer]));
+        ControlFlow::Continue(())
+    }).unwrap();
+    assert_eq!(trace, vec![(0, "SetCell", 0), (1, "MoveCell", 3), (2, "Move", 0), (3, "Write", 3)]);
+    assert_eq!(output, [3]);
+
+    // stopping before the write leaves it out
+    let mut output = Vec::new();
+    let mut tape = [0u8; 4];
+    execute_with_hook(&program, &mut tape, &mut 0, &mut std::io::empty(), &mut output, &Options::default(), |_, instr, _, _| {
+        if *instr == FlatInstr::Write { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+    }).unwrap();
+    assert!(output.is_empty());
+    assert_eq!(tape, [0, 3, 0, 0]);
+}
+
+/// a reader whose every read fails
+struct BrokenInput;
+
+impl std::io::Read for BrokenInput {
+    fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::BrokenPipe.into())
+    }
+}
+
+#[test]
+fn failing_input_is_an_error() {
+    let program = [FlatInstr::Read, FlatInstr::Write, FlatInstr::Read, FlatInstr::Write];
+    let mut tape = [0u8; 4];
+    let mut output = Vec::new();
+    let result = execute(&program, &mut tape, &mut 0, &mut std::io::Read::chain(&b"a"[..], BrokenInput), &mut output, &Options::default(), None);
+    assert_eq!(result, Err(RuntimeError::InputError(std::io::ErrorKind::BrokenPipe)));
+    assert_eq!(output, b"a");
+    assert_eq!(result.unwrap_err().to_string(), "couldn't read input: broken pipe");
+}
+
+#[test]
+fn saturating_cells_stop_at_their_ends() {
+    let add = |program: &[FlatInstr], cell_overflow: CellOverflow| {
+        let mut tape = [255u8, 3];
+        execute(program, &mut tape, &mut 0, &mut std::io::empty(), &mut Vec::new(), &Options { cell_overflow, ..Options::default() }, None).unwrap();
+        tape
+    };
+    assert_eq!(add(&[FlatInstr::Add(5)], CellOverflow::Wrap), [4, 3]);
+    assert_eq!(add(&[FlatInstr::Add(5)], CellOverflow::Saturate), [255, 3]);
+    assert_eq!(add(&[FlatInstr::Sub(5), FlatInstr::AddAt(1, 5u32.wrapping_neg())], CellOverflow::Wrap), [250, 254]);
+    assert_eq!(add(&[FlatInstr::Sub(5), FlatInstr::AddAt(1, 5u32.wrapping_neg())], CellOverflow::Saturate), [250, 0]);
+    // counts past what a cell holds don't get truncated first
+    assert_eq!(add(&[FlatInstr::Sub(300), FlatInstr::AddAt(1, 300)], CellOverflow::Saturate), [0, 255]);
+}
+
+/// a writer and a reader that note down what was done with them, in order
+struct Recorder<'a>(&'a std::cell::RefCell<Vec<&'static str>>);
+
+impl std::io::Write for Recorder<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().push("write");
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.borrow_mut().push("flush");
+        Ok(())
+    }
+}
+
+impl std::io::Read for Recorder<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().push("read");
+        buf[0] = b'y';
+        Ok(1)
+    }
+}
+
+#[test]
+fn prompts_are_flushed_before_reading() {
+    // prints `?`, reads an answer and echoes it
+    let events = std::cell::RefCell::new(Vec::new());
+    let program = flatten(&parse(tokenize("+++++++[>+++++++++<-]>.,.")).unwrap());
+    let mut output = std::io::BufWriter::new(Recorder(&events));
+    execute(&program, &mut [0u8; 4], &mut 0, &mut Recorder(&events), &mut output, &Options::default(), None).unwrap();
+    drop(output);
+    assert_eq!(events.into_inner(), ["write", "flush", "read", "write"]);
+}
+
+/// a writer whose every write fails
+struct BrokenOutput;
+
+impl std::io::Write for BrokenOutput {
+    fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn failing_output_is_an_error() {
+    for program in [&[FlatInstr::Add(1), FlatInstr::Write][..], &[FlatInstr::TapeState]] {
+        let mut tape = [0u8; 4];
+        let result = execute(program, &mut tape, &mut 0, &mut std::io::empty(), &mut BrokenOutput, &Options::default(), None);
+        assert_eq!(result, Err(RuntimeError::OutputError(std::io::ErrorKind::BrokenPipe)));
+    }
+    let mut tape = [0u8; 4];
+    let result = execute(&[FlatInstr::Write], &mut tape, &mut 0, &mut std::io::empty(), &mut BrokenOutput, &Options { numeric_output: true, ..Options::default() }, None);
+    assert_eq!(result.unwrap_err().to_string(), "couldn't write output: broken pipe");
+}
@@ -0,0 +1,109 @@
+use brainfuckers::{execute, flatten, optimize_opcodes, parse, tokenize, FlatInstr, Instruction, Options};
+
+/// the recursive tree walking interpreter `execute` used to be,
+/// kept here as a reference for the flattened one
+fn execute_tree(instructions: &[Instruction], tape: &mut [u8], data_pointer: &mut usize, output: &mut Vec<u8>) {
+    let wrap = |p: usize, offset: i32, len: usize| (p as i64 + offset as i64).rem_euclid(len as i64) as usize;
+    for instr in instructions {
+        match instr {
+            Instruction::Move(offset) => *data_pointer = wrap(*data_pointer, *offset, tape.len()),
+            Instruction::Add(count) => tape[*data_pointer] = tape[*data_pointer].wrapping_add(*count as u8),
+            Instruction::Sub(count) => tape[*data_pointer] = tape[*data_pointer].wrapping_sub(*count as u8),
+            Instruction::AddAt(offset, count) => {
+                let target = wrap(*data_pointer, *offset, tape.len());
+                tape[target] = tape[target].wrapping_add(*count as u8);
+            }
+            Instruction::ResetCell => tape[*data_pointer] = 0,
+            Instruction::SetCell(value) => tape[*data_pointer] = *value as u8,
+            Instruction::ScanCells(right) => {
+                while tape[*data_pointer] != 0 {
+                    *data_pointer = wrap(*data_pointer, if *right { 1 } else { -1 }, tape.len());
+                }
+            }
+            Instruction::MultiplyMove(targets) => {
+                let value = tape[*data_pointer];
+                for (offset, multiplier) in targets {
+                    let target = wrap(*data_pointer, *offset, tape.len());
+                    tape[target] = tape[target].wrapping_add(value.wrapping_mul(*multiplier as u8));
+                }
+                tape[*data_pointer] = 0;
+            }
+            Instruction::MoveCell(offset) => {
+                let target = wrap(*data_pointer, *offset, tape.len());
+                tape[target] = tape[target].wrapping_add(tape[*data_pointer]);
+                tape[*data_pointer] = 0;
+            }
+            Instruction::Write => output.push(tape[*data_pointer]),
+            Instruction::Loop(body) => {
+                while tape[*data_pointer] != 0 {
+                    execute_tree(body, tape, data_pointer, output);
+                }
+            }
+            Instruction::If(body) => if tape[*data_pointer] != 0 {
+                execute_tree(body, tape, data_pointer, output);
+            },
+            Instruction::Read | Instruction::TapeState | Instruction::TapeWindow | Instruction::InfiniteLoop | Instruction::ClearRange(_) | Instruction::Push | Instruction::Pop => unimplemented!(),
+        }
+    }
+}
+
+const PROGRAMS: &[&str] = &[
+    // hello world
+    "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.",
+    // squares of 1 to 10, as raw bytes
+    "++++++++++[>+>[-]<[->>+>+<<<]>>>[-<<<+>>>]<[-<<[->+>>>+<<<<]>>>>[-<<<<+>>>>]<<]<.<<-]",
+    // nested countdowns
+    "+++[>+++[>+++[>+.<-]<-]<-]",
+    // scans on a partially filled tape
+    ">+>++>+++>>+++++<<<<[>]>.[<]>.>>>[<]>>.",
+];
+
+#[test]
+fn flat_execution_matches_tree_execution() {
+    for source in PROGRAMS {
+        let mut opcodes = tokenize(source);
+        optimize_opcodes(&mut opcodes);
+        let tree = parse(opcodes).unwrap();
+
+        let (mut tape, mut data_pointer, mut expected) = ([0u8; 64], 0, Vec::new());
+        execute_tree(&tree, &mut tape, &mut data_pointer, &mut expected);
+
+        let (mut flat_tape, mut flat_data_pointer) = ([0u8; 64], 0);
+        let mut output = Vec::new();
+        execute(&flatten(&tree), &mut flat_tape, &mut flat_data_pointer, &mut std::io::empty(), &mut output, &Options::default(), None).unwrap();
+
+        assert_eq!(output, expected, "output of {}", source);
+        assert_eq!(flat_tape, tape, "tape after {}", source);
+        assert_eq!(flat_data_pointer, data_pointer, "data pointer after {}", source);
+    }
+}
+
+#[test]
+fn loops_become_jump_pairs() {
+    let mut opcodes = tokenize("+[>[.]<]");
+    optimize_opcodes(&mut opcodes);
+    assert_eq!(flatten(&parse(opcodes).unwrap()), vec![
+        FlatInstr::SetCell(1),
+        FlatInstr::JumpIfZero(8),
+        FlatInstr::Move(1),
+        FlatInstr::JumpIfZero(6),
+        FlatInstr::Write,
+        FlatInstr::JumpIfNonZero(4),
+        FlatInstr::Move(-1),
+        FlatInstr::JumpIfNonZero(2),
+    ]);
+}
+
+#[test]
+fn deep_nesting_does_not_overflow_the_stack() {
+    let depth = 5000;
+    let source = "[".repeat(depth) + &"]".repeat(depth) + "+.";
+    let mut opcodes = tokenize(&source);
+    optimize_opcodes(&mut opcodes);
+    let program = flatten(&parse(opcodes).unwrap());
+
+    let mut tape = [0u8; 4];
+    let mut output = Vec::new();
+    execute(&program, &mut tape, &mut 0, &mut std::io::empty(), &mut output, &Options::default(), None).unwrap();
+    assert_eq!(output, vec![1]);
+}
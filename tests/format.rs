@@ -0,0 +1,39 @@
+use brainfuckers::format::format_source;
+
+const MESSY_HELLO: &str = "\
+hello world   from wikipedia
+++++++++  [>++++ [>++>+++>+++>+<<<<-]
+   >+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.
+
+
+  the end";
+
+#[test]
+fn loops_with_loops_inside_are_indented() {
+    assert_eq!(format_source(MESSY_HELLO, true), "\
+hello world   from wikipedia
+++++++++[
+  >++++[>++>+++>+++>+<<<<-]
+  >+>+>->>+[<]<-
+]
+>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.
+
+the end
+");
+    assert_eq!(format_source(MESSY_HELLO, false), "\
+++++++++[
+  >++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-
+]
+>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.
+");
+}
+
+#[test]
+fn formatting_twice_changes_nothing() {
+    for source in [MESSY_HELLO, "[[-]] stray ] left [ open +", "+ one\n\n\n\n[ two\n - three ]four", ""] {
+        for comments in [true, false] {
+            let formatted = format_source(source, comments);
+            assert_eq!(format_source(&formatted, comments), formatted, "{:?}", source);
+        }
+    }
+}
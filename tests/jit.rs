@@ -0,0 +1,91 @@
+#![cfg(feature = "jit")]
+
+use brainfuckers::jit::JitProgram;
+use brainfuckers::{execute, flatten, optimize_opcodes, parse, tokenize, EofBehavior, FlatInstr, Options, RuntimeError};
+
+fn compile(source: &str) -> Vec<FlatInstr> {
+    let mut opcodes = tokenize(source);
+    optimize_opcodes(&mut opcodes);
+    flatten(&parse(opcodes).unwrap())
+}
+
+/// what a run ended with: the error, the tape, the data pointer and the output
+type Outcome = (Result<(), RuntimeError>, Vec<u8>, usize, Vec<u8>);
+
+fn interpreted(program: &[FlatInstr], tape_len: usize, input: &[u8], options: &Options) -> Outcome {
+    let (mut tape, mut data_pointer, mut output) = (vec![0u8; tape_len], 0, Vec::new());
+    let result = execute(program, &mut tape, &mut data_pointer, &mut &input[..], &mut output, options, None);
+    (result, tape, data_pointer, output)
+}
+
+fn jitted(program: &[FlatInstr], tape_len: usize, input: &[u8], options: &Options) -> Outcome {
+    let (mut tape, mut data_pointer, mut output) = (vec![0u8; tape_len], 0, Vec::new());
+    let result = JitProgram::compile(program, tape_len).unwrap().run(&mut tape, &mut data_pointer, &mut &input[..], &mut output, options);
+    (result, tape, data_pointer, output)
+}
+
+#[test]
+fn jitted_programs_do_what_the_interpreter_does() {
+    let programs = [
+        (include_str!("../benches/programs/hello.bf"), &b""[..], EofBehavior::Unchanged),
+        (include_str!("../benches/programs/quine.bf"), b"", EofBehavior::Unchanged),
+        (include_str!("../benches/programs/rot13.bf"), b"Hello, World! ~", EofBehavior::Unchanged),
+        (include_str!("../benches/programs/cat.bf"), b"some text\n", EofBehavior::Unchanged),
+        (">,[>,]<[.<]", b"stressed", EofBehavior::Zero),
+    ];
+    for (source, input, eof) in programs {
+        let program = compile(source);
+        let options = Options { eof, ..Options::default() };
+        let expected = interpreted(&program, 30000, input, &Options { max_steps: Some(10_000_000), ..options.clone() });
+        assert_eq!(expected.0, Ok(()));
+        assert_eq!(jitted(&program, 30000, input, &options), expected);
+    }
+}
+
+#[test]
+fn jitted_programs_wrap_and_fail_like_the_interpreter() {
+    let options = Options::default();
+    for (source, tape_len) in [("<<+>>>>>>>+[-<+>]<<<[->>+<<]", 4), ("+[<+++>-]<.", 3), (">>>>>>>>>>+<<.", 7), ("+[>+]", 5), ("+[]", 3), ("<<[-]+[<]", 4)] {
+        let program = compile(source);
+        assert_eq!(jitted(&program, tape_len, b"", &options), interpreted(&program, tape_len, b"", &options), "{}", source);
+    }
+}
+
+#[test]
+fn random_programs_do_the_same_jitted() {
+    // a fixed seed, so a failure can be reproduced
+    let mut seed: u64 = 0x5eed;
+    let mut random = |below: u64| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 33) % below
+    };
+    let limited = Options { max_steps: Some(20_000), ..Options::default() };
+    let mut compared = 0;
+    for _ in 0..2000 {
+        let mut source = String::new();
+        let mut open = 0;
+        for _ in 0..random(40) {
+            // loops the optimizer turns into moves and multiplies too
+            let piece = ["+", "-", "<", ">", "[", "]", ".", ",", "[-]", "[->+<]", "[-<<+++>>>-->+<<]", ">>>>>>>>>>>>>"][random(12) as usize];
+            match piece {
+                "]" if open == 0 => continue,
+                "]" => open -= 1,
+                "[" => open += 1,
+                _ => (),
+            }
+            source.push_str(piece);
+        }
+        source.push_str(&"]".repeat(open));
+
+        let program = compile(&source);
+        let tape_len = 1 + random(12) as usize;
+        let expected = interpreted(&program, tape_len, b"xyz", &limited);
+        // the jit doesn't count steps, so only programs that end are compared
+        if matches!(expected.0, Err(RuntimeError::StepLimitReached(..))) {
+            continue;
+        }
+        assert_eq!(jitted(&program, tape_len, b"xyz", &Options::default()), expected, "{}", source);
+        compared += 1;
+    }
+    assert!(compared > 1000);
+}
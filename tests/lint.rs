@@ -0,0 +1,34 @@
+use brainfuckers::{any_instruction, find_out_of_bounds, optimize_opcodes, parse, tokenize, Instruction};
+
+fn lint(source: &str, tape_size: usize) -> Option<i64> {
+    let mut opcodes = tokenize(source);
+    optimize_opcodes(&mut opcodes);
+    find_out_of_bounds(&parse(opcodes).unwrap(), tape_size)
+}
+
+#[test]
+fn moves_off_the_tape_are_found() {
+    assert_eq!(lint(&">".repeat(40000), 30000), Some(40000));
+    assert_eq!(lint("+<", 30000), Some(-1));
+    // offsets count too, even if the pointer itself never gets there
+    assert_eq!(lint(">>+<<", 2), Some(2));
+    // and so do loop bodies
+    assert_eq!(lint("+[>>>>.<<<<-]", 4), Some(4));
+    assert_eq!(lint(">>>+<<<", 4), None);
+}
+
+#[test]
+fn the_pointer_is_lost_after_scans_and_moving_loops() {
+    assert_eq!(lint("+[>+]<<<<", 8), None);
+    assert_eq!(lint("[>]<<<<", 8), None);
+    // a balanced loop leaves the pointer where it was
+    assert_eq!(lint("+[>+.<-]<", 8), Some(-1));
+}
+
+#[test]
+fn instructions_are_found_inside_loops() {
+    let program = parse(tokenize("+[>[.]<-]")).unwrap();
+    assert!(any_instruction(&program, &|instr| matches!(instr, Instruction::Write)));
+    assert!(!any_instruction(&program, &|instr| matches!(instr, Instruction::Read)));
+    assert!(!any_instruction(&[], &|_| true));
+}
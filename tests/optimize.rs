@@ -0,0 +1,331 @@
+use brainfuckers::{coalesce_moves, dead_stores, execute, flatten, fold_offsets, known_zero, multiply_loops, optimize_logged, optimize_opcodes, optimize_with, parse, reset_cells, run, run_length, scan_cells, set_cells, tokenize, tokenize_with_positions, write_ir, FlatInstr, OpCode, Options, Pass, Position, Rewrite, RuntimeError, PASSES};
+
+fn optimized(source: &str) -> Vec<OpCode> {
+    let mut opcodes = tokenize(source);
+    optimize_opcodes(&mut opcodes);
+    opcodes
+}
+
+#[test]
+fn multiply_loops_become_multiply_move() {
+    assert_eq!(optimized("[->+>+++<<]"), vec![OpCode::MultiplyMove(vec![(1, 1), (2, 3)])]);
+    assert_eq!(optimized("[<<->+>-]"), vec![OpCode::MultiplyMove(vec![(-2, u32::MAX), (-1, 1)])]);
+    // unbalanced pointer motion or a counter that isn't decremented once stays a loop
+    assert_eq!(optimized("[->+]").first(), Some(&OpCode::LoopBegin));
+    assert_eq!(optimized("[-->+<]").first(), Some(&OpCode::LoopBegin));
+}
+
+#[test]
+fn single_target_move_loops_become_move_cell() {
+    assert_eq!(optimized("[->+<]"), vec![OpCode::MoveCell(1)]);
+    assert_eq!(optimized("[-<<+>>]"), vec![OpCode::MoveCell(-2)]);
+    assert_eq!(optimized("[<+>-]"), vec![OpCode::MoveCell(-1)]);
+    // anything but a plain copy stays a multiply
+    assert_eq!(optimized("[->++<]"), vec![OpCode::MultiplyMove(vec![(1, 2)])]);
+
+    let mut tape = [0u8; 8];
+    run(">+++++[-<+>]>>++[-<+>]", &mut tape).unwrap();
+    assert_eq!(&tape[..4], &[5, 0, 2, 0]);
+}
+
+#[test]
+fn multiply_move_matches_loop_semantics() {
+    let mut tape = [0u8; 8];
+    run("+++++[->++>+++<<]", &mut tape).unwrap();
+    assert_eq!(&tape[..3], &[0, 10, 15]);
+}
+
+#[test]
+fn offset_adds_skip_pointer_shuffling() {
+    assert_eq!(optimized(">>>+++++<<<"), vec![OpCode::AddAt(3, 5)]);
+    assert_eq!(optimized("+>+>-"), vec![OpCode::SetCell(1), OpCode::AddAt(1, 1), OpCode::AddAt(2, u32::MAX), OpCode::Move(2)]);
+
+    let mut tape = [0u8; 8];
+    run("+>++>---<<[>>+<<-]", &mut tape).unwrap();
+    assert_eq!(&tape[..3], &[0, 2, 254]);
+}
+
+#[test]
+fn scans_at_the_end_of_a_program_keep_their_direction() {
+    assert_eq!(optimized("+[>]"), vec![OpCode::SetCell(1), OpCode::ScanCells(true)]);
+    assert_eq!(optimized("+[<]"), vec![OpCode::SetCell(1), OpCode::ScanCells(false)]);
+    assert_eq!(optimized("[>]+"), vec![OpCode::ScanCells(true), OpCode::SetCell(1)]);
+    assert_eq!(optimized("[<]>"), vec![OpCode::ScanCells(false), OpCode::Move(1)]);
+}
+
+#[test]
+fn instruction_tree_is_indented_by_loop_depth() {
+    let mut ir = Vec::new();
+    write_ir(&parse(optimized("+[>,[.,]<-]")).unwrap(), &mut ir).unwrap();
+    assert_eq!(String::from_utf8(ir).unwrap(), "\
+SetCell(1)
+Loop {
+  Move(1)
+  Read
+  Loop {
+    Write
+    Read
+  }
+  AddAt(-1, 4294967295)
+  Move(-1)
+}
+");
+}
+
+#[test]
+fn optimizer_runs_until_nothing_changes() {
+    // the moves only turn into a single step after the first round
+    assert_eq!(optimized("[>><]"), vec![OpCode::ScanCells(true)]);
+    // `+-+` takes a round to merge into the `+` that makes it a reset
+    assert_eq!(optimized("[+-+]"), vec![OpCode::ResetCell]);
+    assert_eq!(optimized("++--"), vec![]);
+}
+
+#[test]
+fn cancelling_moves_leave_the_pointer_alone() {
+    assert_eq!(optimized("+><+"), vec![OpCode::SetCell(2)]);
+
+    let mut tape = [0u8; 8];
+    run(">+<>+<<>", &mut tape).unwrap();
+    assert_eq!(&tape[..2], &[0, 2]);
+}
+
+#[test]
+fn reset_pass_only_replaces_clear_loops() {
+    let mut opcodes = tokenize("[-]+[+]");
+    reset_cells(&mut opcodes);
+    assert_eq!(opcodes, vec![OpCode::ResetCell, OpCode::Increment, OpCode::ResetCell]);
+}
+
+#[test]
+fn scan_pass_keeps_the_direction() {
+    let mut opcodes = tokenize("[<][>]");
+    scan_cells(&mut opcodes);
+    assert_eq!(opcodes, vec![OpCode::ScanCells(false), OpCode::ScanCells(true)]);
+}
+
+#[test]
+fn multiply_pass_works_on_already_merged_opcodes() {
+    let mut opcodes = vec![OpCode::LoopBegin, OpCode::Sub(1), OpCode::AddAt(2, 3), OpCode::LoopEnd];
+    multiply_loops(&mut opcodes);
+    assert_eq!(opcodes, vec![OpCode::MultiplyMove(vec![(2, 3)])]);
+}
+
+#[test]
+fn run_length_pass_merges_adds_and_subs() {
+    let mut opcodes = tokenize("+++->--");
+    run_length(&mut opcodes);
+    assert_eq!(opcodes, vec![OpCode::Add(2), OpCode::IncrementPointer, OpCode::Sub(2)]);
+
+    // the net change, or nothing if it cancels out
+    assert_eq!(optimized(",+++--"), vec![OpCode::Read, OpCode::Add(1)]);
+    assert_eq!(optimized(",++--"), vec![OpCode::Read]);
+    assert_eq!(optimized(",+--"), vec![OpCode::Read, OpCode::Sub(1)]);
+    let mut opcodes = vec![OpCode::Add(3), OpCode::Sub(2), OpCode::Add(u32::MAX)];
+    run_length(&mut opcodes);
+    assert_eq!(opcodes, vec![]);
+}
+
+#[test]
+fn moves_pass_drops_moves_that_cancel_out() {
+    let mut opcodes = tokenize(">>+<><");
+    coalesce_moves(&mut opcodes);
+    assert_eq!(opcodes, vec![OpCode::Move(2), OpCode::Increment, OpCode::Move(-1)]);
+}
+
+#[test]
+fn offsets_pass_defers_the_move() {
+    let mut opcodes = vec![OpCode::Move(2), OpCode::Add(1), OpCode::Move(-1), OpCode::Sub(1)];
+    fold_offsets(&mut opcodes);
+    assert_eq!(opcodes, vec![OpCode::AddAt(2, 1), OpCode::AddAt(1, u32::MAX), OpCode::Move(1)]);
+}
+
+#[test]
+fn unoptimized_opcodes_still_parse() {
+    let mut tape = [0u8; 8];
+    let mut opcodes = tokenize("++[>+++<-]");
+    optimize_with(&mut opcodes, &[]);
+    let program = flatten(&parse(opcodes).unwrap());
+    execute(&program, &mut tape, &mut 0, &mut std::io::empty(), &mut Vec::new(), &Options::default(), None).unwrap();
+    assert_eq!(&tape[..2], &[0, 6]);
+}
+
+#[test]
+fn dead_stores_before_a_reset_are_dropped() {
+    assert_eq!(optimized("+++[-]"), vec![OpCode::ResetCell]);
+    assert_eq!(optimized("[-][-]"), vec![OpCode::ResetCell]);
+    assert_eq!(optimized("+[>+.<-][-]"), optimized("+[>+.<-]"));
+    // a scan stops on a zero cell
+    assert_eq!(optimized("[>][-]"), vec![OpCode::ScanCells(true)]);
+    // a move in between means it's a different cell
+    assert_eq!(optimized("+>[-]"), vec![OpCode::SetCell(1), OpCode::Move(1), OpCode::ResetCell]);
+    assert_eq!(optimized("[-]>>[-]"), vec![OpCode::ResetCell, OpCode::Move(2), OpCode::ResetCell]);
+
+    let mut opcodes = vec![OpCode::Decrement, OpCode::Add(2), OpCode::ResetCell, OpCode::ResetCell];
+    dead_stores(&mut opcodes);
+    assert_eq!(opcodes, vec![OpCode::ResetCell]);
+}
+
+#[test]
+fn neighbouring_resets_become_clear_range() {
+    assert_eq!(optimized("[-]>[-]>[-]"), vec![OpCode::ClearRange(3)]);
+    assert_eq!(optimized(",>[-]>[-]<<."), vec![OpCode::Read, OpCode::Move(1), OpCode::ClearRange(2), OpCode::Move(-2), OpCode::Write]);
+    // only to the right, one cell at a time
+    assert_eq!(optimized(",[-]<[-]"), vec![OpCode::Read, OpCode::ResetCell, OpCode::Move(-1), OpCode::ResetCell]);
+    assert_eq!(optimized(",[-]>>[-]"), vec![OpCode::Read, OpCode::ResetCell, OpCode::Move(2), OpCode::ResetCell]);
+
+    let mut tape = [5u8; 6];
+    run(">[-]>[-]>[-]>[-]+", &mut tape).unwrap();
+    assert_eq!(tape, [5, 0, 0, 0, 1, 5]);
+}
+
+#[test]
+fn loops_around_a_clear_range_stay_loops() {
+    // the loop never runs, so the 1 next to the first cell survives
+    let source = ">+<[[-]>[-]]>.";
+    assert_eq!(optimized(source), vec![OpCode::AddAt(1, 1), OpCode::LoopBegin, OpCode::ClearRange(2), OpCode::IfEnd, OpCode::Move(1), OpCode::Write]);
+    let options = Options { numeric_output: true, ..Options::default() };
+    let outputs = [tokenize(source), optimized(source)].map(|opcodes| {
+        let mut output = Vec::new();
+        execute(&flatten(&parse(opcodes).unwrap()), &mut [0u8; 4], &mut 0, &mut std::io::empty(), &mut output, &options, None).unwrap();
+        output
+    });
+    assert_eq!(outputs[0], b"1 ");
+    assert_eq!(outputs[1], outputs[0]);
+}
+
+#[test]
+fn resets_are_found_after_other_adds() {
+    let mut opcodes = tokenize("++[-]");
+    optimize_with(&mut opcodes, &[Pass::RunLength, Pass::Reset]);
+    assert_eq!(opcodes, vec![OpCode::Add(2), OpCode::ResetCell]);
+    let mut opcodes = tokenize("+[-]-[+]");
+    optimize_with(&mut opcodes, &[Pass::Reset, Pass::RunLength]);
+    assert_eq!(opcodes, vec![OpCode::Add(1), OpCode::ResetCell, OpCode::Sub(1), OpCode::ResetCell]);
+    // with every pass the adds are dead
+    assert_eq!(optimized("++[-]"), vec![OpCode::ResetCell]);
+}
+
+#[test]
+fn adds_to_known_zero_cells_become_set_cell() {
+    assert_eq!(optimized("++++"), vec![OpCode::SetCell(4)]);
+    assert_eq!(optimized("+[>+<-]++."), vec![OpCode::SetCell(1), OpCode::MoveCell(1), OpCode::SetCell(2), OpCode::Write]);
+    assert_eq!(optimized(",+[>]-"), vec![OpCode::Read, OpCode::Add(1), OpCode::ScanCells(true), OpCode::SetCell(u32::MAX)]);
+
+    // the cell isn't known anymore once it's written or the pointer moved
+    let mut opcodes = vec![OpCode::Add(2), OpCode::Write, OpCode::Sub(1), OpCode::ResetCell, OpCode::Move(1), OpCode::Add(1), OpCode::LoopBegin, OpCode::Sub(1), OpCode::LoopEnd, OpCode::Decrement];
+    known_zero(&mut opcodes);
+    assert_eq!(opcodes, vec![OpCode::SetCell(2), OpCode::Write, OpCode::Sub(1), OpCode::ResetCell, OpCode::Move(1), OpCode::Add(1), OpCode::LoopBegin, OpCode::Sub(1), OpCode::LoopEnd, OpCode::SetCell(u32::MAX)]);
+}
+
+#[test]
+fn empty_loops_become_infinite_loop() {
+    assert_eq!(optimized("+[]"), vec![OpCode::SetCell(1), OpCode::InfiniteLoop]);
+    assert_eq!(optimized("[[]]"), vec![OpCode::InfiniteLoop]);
+    assert_eq!(optimized("[+-]"), vec![OpCode::InfiniteLoop]);
+}
+
+#[test]
+fn loops_ending_in_a_loop_keep_their_brackets() {
+    assert_eq!(optimized("++[-[]]"), vec![OpCode::SetCell(2), OpCode::LoopBegin, OpCode::Sub(1), OpCode::InfiniteLoop, OpCode::IfEnd]);
+    let program = flatten(&parse(optimized("++[-[]]")).unwrap());
+    let result = execute(&program, &mut [0u8; 4], &mut 0, &mut std::io::empty(), &mut Vec::new(), &Options::default(), None);
+    assert!(matches!(result, Err(RuntimeError::InfiniteLoop(_))));
+
+    let mut tape = [3u8, 0];
+    run("[-[-]]+", &mut tape).unwrap();
+    assert_eq!(tape, [1, 0]);
+    // clearing the cell in the loop still clears it, however it gets there
+    assert_eq!(optimized("[-[-]]"), vec![OpCode::ResetCell]);
+}
+
+#[test]
+fn reset_then_add_becomes_set_cell() {
+    assert_eq!(optimized("[-]+++"), vec![OpCode::SetCell(3)]);
+    assert_eq!(optimized("[-]--"), vec![OpCode::SetCell(u32::MAX - 1)]);
+    // whatever was there before gets overwritten
+    assert_eq!(optimized("+++[-]++"), vec![OpCode::SetCell(2)]);
+    assert_eq!(optimized("[-]++[-]+"), vec![OpCode::SetCell(1)]);
+
+    let mut opcodes = vec![OpCode::ResetCell, OpCode::Add(2), OpCode::Sub(2), OpCode::Move(1)];
+    set_cells(&mut opcodes);
+    assert_eq!(opcodes, vec![OpCode::ResetCell, OpCode::Move(1)]);
+
+    let mut tape = [7u8; 4];
+    run("[-]+++>[-]-", &mut tape).unwrap();
+    assert_eq!(&tape[..2], &[3, 255]);
+}
+
+#[test]
+fn optimized_opcodes_keep_the_position_of_their_first_symbol() {
+    let mut tokens = tokenize_with_positions("+++\n>[-]<\n  [->+<].");
+    optimize_opcodes(&mut tokens);
+    let at = |line, column| Position { line, column };
+    assert_eq!(tokens, vec![
+        (OpCode::SetCell(3), at(1, 1)),
+        (OpCode::Move(1), at(2, 1)),
+        (OpCode::ResetCell, at(2, 2)),
+        (OpCode::Move(-1), at(2, 5)),
+        (OpCode::MoveCell(1), at(3, 3)),
+        (OpCode::Write, at(3, 9)),
+    ]);
+}
+
+#[test]
+fn nested_loops_are_optimized_at_every_depth() {
+    assert_eq!(optimized("[[-]]"), vec![OpCode::ResetCell]);
+    assert_eq!(optimized("[[[->+<]]]"), vec![OpCode::MoveCell(1)]);
+    assert_eq!(optimized("[[>]]"), vec![OpCode::ScanCells(true)]);
+    // a clear inside a bigger loop body collapses just the same
+    assert_eq!(optimized("+[>[-]<-]"), vec![OpCode::SetCell(1), OpCode::LoopBegin, OpCode::Move(1), OpCode::ResetCell, OpCode::AddAt(-1, u32::MAX), OpCode::Move(-1), OpCode::LoopEnd]);
+}
+
+#[test]
+fn offsets_pass_folds_across_earlier_offsets() {
+    let mut opcodes = vec![OpCode::AddAt(2, 1), OpCode::Move(1), OpCode::Add(2), OpCode::Move(1), OpCode::AddAt(-2, 3), OpCode::Write];
+    fold_offsets(&mut opcodes);
+    assert_eq!(opcodes, vec![OpCode::AddAt(2, 1), OpCode::AddAt(1, 2), OpCode::Add(3), OpCode::Move(2), OpCode::Write]);
+}
+
+#[test]
+fn optimize_logged_reports_every_rewrite() {
+    let passes: Vec<Pass> = PASSES.iter().map(|(_, pass)| *pass).collect();
+    let mut logged = tokenize("+++[-]>[-][-]");
+    let log = optimize_logged(&mut logged, &passes);
+    assert_eq!(logged, optimized("+++[-]>[-][-]"));
+
+    let rewrite = |pass, at, removed: &str, inserted| Rewrite { pass, round: 1, at, removed: tokenize(removed), inserted };
+    assert_eq!(log, vec![
+        rewrite(Pass::Reset, 3, "[-]", vec![OpCode::ResetCell]),
+        rewrite(Pass::Reset, 7, "[-]", vec![OpCode::ResetCell]),
+        rewrite(Pass::Reset, 10, "[-]", vec![OpCode::ResetCell]),
+        // the indices are where the opcodes were when the pass ran
+        rewrite(Pass::DeadStores, 0, "+++", vec![]),
+        Rewrite { pass: Pass::DeadStores, round: 1, at: 6, removed: vec![OpCode::ResetCell], inserted: vec![] },
+        rewrite(Pass::Moves, 1, ">", vec![OpCode::Move(1)]),
+        Rewrite { pass: Pass::ClearRange, round: 1, at: 0, removed: vec![OpCode::ResetCell, OpCode::Move(1), OpCode::ResetCell], inserted: vec![OpCode::ClearRange(2)] },
+    ]);
+}
+
+#[test]
+fn optimize_logged_matches_optimize_with() {
+    let source = include_str!("../benches/programs/rot13.bf");
+    let passes: Vec<Pass> = PASSES.iter().map(|(_, pass)| *pass).collect();
+    let mut logged = tokenize(source);
+    assert!(!optimize_logged(&mut logged, &passes).is_empty());
+    assert_eq!(logged, optimized(source));
+}
+
+#[test]
+fn loops_that_end_on_a_zero_cell_become_ifs() {
+    assert_eq!(optimized("[>+<[-]]"), vec![OpCode::LoopBegin, OpCode::AddAt(1, 1), OpCode::ResetCell, OpCode::IfEnd]);
+    // it's whatever cell the pointer ends up on that has to be zero
+    assert_eq!(optimized("[[-]>]").last(), Some(&OpCode::LoopEnd));
+    assert_eq!(optimized("[.[>]]").last(), Some(&OpCode::IfEnd));
+
+    let program = flatten(&parse(optimized("+[>+<[-]]>.")).unwrap());
+    assert!(!program.iter().any(|instr| matches!(instr, FlatInstr::JumpIfNonZero(_))));
+    let mut output = Vec::new();
+    execute(&program, &mut [0u8; 4], &mut 0, &mut std::io::empty(), &mut output, &Options::default(), None).unwrap();
+    assert_eq!(output, [1]);
+}
@@ -0,0 +1,55 @@
+use brainfuckers::{execute, flatten, optimize_opcodes, parse, tokenize, EofBehavior, Options};
+
+/// runs `source` on a fresh tape with `input` and returns what it wrote
+fn output_with_eof(source: &str, input: &[u8], eof: EofBehavior) -> Vec<u8> {
+    let mut opcodes = tokenize(source);
+    optimize_opcodes(&mut opcodes);
+    let program = flatten(&parse(opcodes).unwrap());
+
+    let mut tape = vec![0u8; 30000];
+    let mut output = Vec::new();
+    execute(&program, &mut tape, &mut 0, &mut &input[..], &mut output, &Options { eof, ..Options::default() }, None).unwrap();
+    output
+}
+
+/// like most brainfuck programs, these expect a zero at the end of input
+fn output_of(source: &str, input: &[u8]) -> Vec<u8> {
+    output_with_eof(source, input, EofBehavior::Zero)
+}
+
+#[test]
+fn hello_world() {
+    assert_eq!(output_of(include_str!("../benches/programs/hello.bf"), b""), b"Hello World!\n");
+}
+
+#[test]
+fn cat() {
+    assert_eq!(output_of(",[.,]", b"meow\nmeow\n"), b"meow\nmeow\n");
+}
+
+#[test]
+fn reverse() {
+    assert_eq!(output_of(">,[>,]<[.<]", b"stressed"), b"desserts");
+}
+
+#[test]
+fn rot13() {
+    // this one relies on the cell staying unchanged at the end of input
+    let source = include_str!("../benches/programs/rot13.bf");
+    assert_eq!(output_with_eof(source, b"Hello, World! xyz", EofBehavior::Unchanged), b"Uryyb, Jbeyq! klm");
+    assert_eq!(output_with_eof(source, b"Uryyb, Jbeyq! klm", EofBehavior::Unchanged), b"Hello, World! xyz");
+}
+
+#[test]
+fn multiply() {
+    // multiplies the two input bytes, the product wraps at 256
+    let source = ",>,<[->[->+>+<<]>>[-<<+>>]<<<]>>.";
+    assert_eq!(output_of(source, &[6, 7]), [42]);
+    assert_eq!(output_of(source, &[0, 9]), [0]);
+    assert_eq!(output_of(source, &[20, 13]), [4]);
+}
+
+#[test]
+fn nested_loops() {
+    assert_eq!(output_of(include_str!("../benches/programs/loops.bf"), b""), b"ZYXWVUTSRQPONMLKJIHGFEDCBA\n");
+}
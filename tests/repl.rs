@@ -0,0 +1,39 @@
+use brainfuckers::repl::Repl;
+use brainfuckers::{Options, PASSES};
+
+fn session(lines: &str) -> String {
+    let passes = PASSES.iter().map(|(_, pass)| *pass).collect();
+    let mut output = Vec::new();
+    Repl::new(lines.as_bytes(), passes).run(&mut [0u8; 8], &mut 0, &mut std::io::empty(), &mut output, &Options::default());
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn tape_is_kept_between_lines() {
+    assert_eq!(session("+++>++\n[<+>-]<\n"), "> [3 >2]\n> [>5]\n> ");
+}
+
+#[test]
+fn bad_lines_are_skipped() {
+    assert_eq!(session("++\n]+\n+[\n+\n"),
+        "> [>2]\n> error: stray closing bracket at line 1, column 1\n> error: stray opening bracket at line 1, column 2\n> [>3]\n> ");
+}
+
+#[test]
+fn output_gets_a_line_of_its_own() {
+    let lines = "++++++++[>++++++++<-]>+.\n";
+    assert_eq!(session(lines), "> A\n[0 >65]\n> ");
+}
+
+#[test]
+fn reset_clears_the_tape() {
+    assert_eq!(session("+>++\n:reset\n+\n"), "> [1 >2]\n> tape cleared\n[>0]\n> [>1]\n> ");
+}
+
+#[test]
+fn lines_dont_have_to_be_utf8() {
+    let passes = PASSES.iter().map(|(_, pass)| *pass).collect();
+    let mut output = Vec::new();
+    Repl::new(&b"+\xff+ caf\xe9\n+\n"[..], passes).run(&mut [0u8; 8], &mut 0, &mut std::io::empty(), &mut output, &Options::default());
+    assert_eq!(String::from_utf8(output).unwrap(), "> [>2]\n> [>3]\n> ");
+}
@@ -0,0 +1,153 @@
+use std::ops::ControlFlow;
+
+use brainfuckers::{balance_brackets, check_brackets, execute, flatten, optimize_opcodes, parse, run, run_at, run_bytes, run_captured, run_with_hook, remap, run_str, strip_comments, tokenize, tokenize_with_comments, Error, Lexeme, OpCode, Options, ParseError, Position, Tee};
+
+const HELLO_WORLD: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+#[test]
+fn hello_world_leaves_expected_tape() {
+    let mut tape = [0u8; 16];
+    run(HELLO_WORLD, &mut tape).unwrap();
+
+    assert_eq!(&tape[..7], &[0, 0, 72, 100, 87, 33, 10]);
+    assert!(tape[7..].iter().all(|&cell| cell == 0));
+}
+
+#[test]
+fn programs_can_share_a_tape() {
+    let mut memory = [7u8; 8];
+    let tape = &mut memory[2..6];
+    let mut data_pointer = 0;
+    // the first one leaves a value behind and the pointer on it,
+    // the second one carries on from there
+    run_at("[-]+++>[-]++", tape, &mut data_pointer).unwrap();
+    assert_eq!((&tape[..], data_pointer), (&[3, 2, 7, 7][..], 1));
+    run_at("+[<+>-]", tape, &mut data_pointer).unwrap();
+    assert_eq!((&tape[..], data_pointer), (&[6, 0, 7, 7][..], 1));
+    assert_eq!(memory, [7, 7, 6, 0, 7, 7, 7, 7]);
+}
+
+#[test]
+fn every_run_function_works_on_a_used_tape() {
+    let mut tape = [5u8, 0];
+    run("+", &mut tape).unwrap();
+    assert_eq!(tape[0], 6);
+    run_with_hook("+", &mut tape, |_, _, _, _| ControlFlow::Continue(())).unwrap();
+    assert_eq!(tape[0], 7);
+    run_captured("+", &mut tape).unwrap();
+    assert_eq!(tape[0], 8);
+}
+
+#[cfg(not(feature = "stack"))]
+#[test]
+fn stack_symbols_are_comments_without_the_feature() {
+    assert_eq!(run_str("+:;.", "").unwrap(), "\x01");
+}
+
+#[test]
+fn unbalanced_brackets_are_errors() {
+    let mut tape = [0u8; 16];
+    assert_eq!(run("+]", &mut tape), Err(Error::Parse(ParseError::StrayClosingBracket(Position { line: 1, column: 2 }))));
+    assert_eq!(run("+[+", &mut tape), Err(Error::Parse(ParseError::StrayOpeningBracket(Position { line: 1, column: 2 }))));
+}
+
+#[test]
+fn bracket_errors_point_into_the_source() {
+    let source = "a comment\n+++[>+\n  [-]\n  ]]  more comment";
+    assert_eq!(check_brackets(source), Err(ParseError::StrayClosingBracket(Position { line: 4, column: 4 })));
+    assert_eq!(check_brackets("[\r\n[ünïcode]"), Err(ParseError::StrayOpeningBracket(Position { line: 1, column: 1 })));
+    // the optimizer shuffles opcodes around, so `parse` alone can only count them
+    assert_eq!(parse(tokenize("+++]")).err(), Some(ParseError::StrayClosingBracket(Position { line: 1, column: 4 })));
+}
+
+#[test]
+fn cells_wrap_at_their_width() {
+    let source = "+".repeat(300) + "[->+>+<<]>>" + &"-".repeat(301);
+
+    let mut narrow = [0u8; 4];
+    run(&source, &mut narrow).unwrap();
+    assert_eq!(&narrow[..3], &[0, 44, 255]);
+
+    let mut wide = [0u16; 4];
+    run(&source, &mut wide).unwrap();
+    assert_eq!(&wide[..3], &[0, 300, u16::MAX]);
+
+    let mut wider = [0u32; 4];
+    run(&source, &mut wider).unwrap();
+    assert_eq!(&wider[..3], &[0, 300, u32::MAX]);
+}
+
+#[test]
+fn comments_can_be_stripped() {
+    assert_eq!(tokenize(&strip_comments("/* +++ */")), vec![]);
+    assert_eq!(tokenize(&strip_comments("+ ; add one, then print.\n.")), vec![OpCode::Increment, OpCode::Write]);
+    assert_eq!(tokenize(&strip_comments("/**/+/* never closed, -")), vec![OpCode::Increment]);
+    // blanked out rather than removed, so brackets keep their place
+    assert_eq!(check_brackets(&strip_comments("/* [\n */ ]")), Err(ParseError::StrayClosingBracket(Position { line: 2, column: 5 })));
+}
+
+#[test]
+fn aliases_are_remapped_in_place() {
+    let aliases = [("inc".to_string(), '+'), ("i".to_string(), '>'), ("🡒".to_string(), '.')];
+    // the longer alias wins, and the symbols keep their meaning
+    assert_eq!(remap("inc i🡒-", &aliases), "+   >.-");
+    // padded, so brackets keep their place
+    assert_eq!(check_brackets(&remap("inc ] ]", &[("inc".to_string(), '[')])), Err(ParseError::StrayClosingBracket(Position { line: 1, column: 7 })));
+}
+
+#[test]
+fn output_can_be_captured() {
+    assert_eq!(run_str(HELLO_WORLD, ""), Ok("Hello World!\n".to_string()));
+    assert_eq!(run_str(",.,.,.,.", "echo"), Ok("echo".to_string()));
+    assert_eq!(run_bytes("-.", b""), Ok(vec![255]));
+    assert_eq!(run_str("-.", ""), Ok("\u{fffd}".to_string()));
+    assert_eq!(run_bytes("+]", b""), Err(Error::Parse(ParseError::StrayClosingBracket(Position { line: 1, column: 2 }))));
+}
+
+#[test]
+fn comments_are_kept_between_symbols() {
+    let at = |line, column| Position { line, column };
+    let source = "add one +\n[. print é]";
+    let lexemes = tokenize_with_comments(source);
+    assert_eq!(lexemes, vec![
+        Lexeme::Comment("add one ", at(1, 1)),
+        Lexeme::Op(OpCode::Increment, at(1, 9)),
+        Lexeme::Comment("\n", at(1, 10)),
+        Lexeme::Op(OpCode::LoopBegin, at(2, 1)),
+        Lexeme::Op(OpCode::Write, at(2, 2)),
+        Lexeme::Comment(" print é", at(2, 3)),
+        Lexeme::Op(OpCode::LoopEnd, at(2, 11)),
+    ]);
+}
+
+#[test]
+fn tee_writes_to_both_sides() {
+    let mut opcodes = tokenize(HELLO_WORLD);
+    optimize_opcodes(&mut opcodes);
+    let program = flatten(&parse(opcodes).unwrap());
+    let (mut first, mut second) = (Vec::new(), Vec::new());
+    execute(&program, &mut [0u8; 16], &mut 0, &mut std::io::empty(), &mut Tee::new(&mut first, &mut second), &Options::default(), None).unwrap();
+    assert_eq!(first, b"Hello World!\n");
+    assert_eq!(second, first);
+}
+
+#[test]
+fn run_captured_returns_what_was_written() {
+    let mut tape = [0u8; 16];
+    assert_eq!(run_captured(HELLO_WORLD, &mut tape).unwrap(), b"Hello World!\n");
+    assert_eq!(tape[2], 72);
+    assert_eq!(run_captured("+[", &mut tape), Err(Error::Parse(ParseError::StrayOpeningBracket(Position { line: 1, column: 2 }))));
+}
+
+#[test]
+fn balancing_drops_stray_closing_brackets_and_closes_the_rest() {
+    let mut opcodes = tokenize("++]");
+    assert_eq!(balance_brackets(&mut opcodes), 1);
+    assert_eq!(opcodes, tokenize("++"));
+    let mut opcodes = tokenize("+[>[++");
+    assert_eq!(balance_brackets(&mut opcodes), 2);
+    assert_eq!(opcodes, tokenize("+[>[++]]"));
+    let mut opcodes = tokenize("][+]");
+    assert_eq!(balance_brackets(&mut opcodes), 1);
+    assert!(parse(opcodes).is_ok());
+}
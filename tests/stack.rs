@@ -0,0 +1,52 @@
+#![cfg(feature = "stack")]
+
+use brainfuckers::bytecode::{decode, encode};
+use brainfuckers::format::format_source;
+use brainfuckers::transpile::to_rust;
+use brainfuckers::{flatten, optimize_opcodes, parse, run, run_str, tokenize, EofBehavior, Error, FlatInstr, OpCode, RuntimeError};
+
+fn compile(source: &str) -> Vec<FlatInstr> {
+    let mut opcodes = tokenize(source);
+    optimize_opcodes(&mut opcodes);
+    flatten(&parse(opcodes).unwrap())
+}
+
+#[test]
+fn colon_and_semicolon_are_push_and_pop() {
+    assert_eq!(tokenize("+:-;"), [OpCode::Increment, OpCode::Push, OpCode::Decrement, OpCode::Pop]);
+}
+
+#[test]
+fn pushed_cells_pop_back_in_reverse_order() {
+    assert_eq!(run_str(",:,:,:;.;.;.", "abc").unwrap(), "cba");
+}
+
+#[test]
+fn popping_an_empty_stack_is_an_error() {
+    let mut tape = [0u8; 4];
+    assert_eq!(run("+:;;", &mut tape), Err(Error::Runtime(RuntimeError::EmptyStack(3))));
+}
+
+#[test]
+fn the_optimizer_keeps_values_on_the_stack() {
+    // the cell is pushed before the loop clears it, so the pop has to bring it back
+    let mut tape = [0u8; 4];
+    run("+++:[-];>+:<:;>;", &mut tape).unwrap();
+    assert_eq!(tape, [3, 1, 0, 0]);
+}
+
+#[test]
+fn stack_programs_round_trip_through_bytecode() {
+    let program = compile("+[:>;<-]");
+    assert_eq!(decode(&encode(&program)).unwrap(), program);
+}
+
+#[test]
+fn stack_programs_format_and_transpile() {
+    assert_eq!(format_source("+ : [ ; ]", false), "+:[;]\n");
+
+    let mut out = Vec::new();
+    to_rust(&parse(tokenize(":;")).unwrap(), 8, EofBehavior::Unchanged, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("let mut stack = Vec::new();") && out.contains("stack.push(tape[*ptr]);"));
+}
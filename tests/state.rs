@@ -0,0 +1,50 @@
+use brainfuckers::state::{decode, encode, StateError, VmState};
+use brainfuckers::{flatten, optimize_opcodes, parse, tokenize, EofBehavior, FlatInstr, Options, RuntimeError};
+
+fn compile(source: &str) -> Vec<FlatInstr> {
+    let mut opcodes = tokenize(source);
+    optimize_opcodes(&mut opcodes);
+    flatten(&parse(opcodes).unwrap())
+}
+
+#[test]
+fn states_round_trip() {
+    let program = compile("+[>+<-]");
+    let state = VmState { tape: vec![1u16, 0xbeef, 0, 7], data_pointer: 3, instr_pointer: 2, steps: 12, input_read: 5, stack: vec![3, 0xffff] };
+    assert_eq!(decode(&encode(&state, &program), &program).unwrap(), state);
+}
+
+#[test]
+fn resumed_runs_match_uninterrupted_ones() {
+    let program = compile(",[>+++[->++<]<-.,]>>.");
+    let input = b"\x03\x05\x02";
+    let options = Options { eof: EofBehavior::Zero, ..Options::default() };
+
+    let mut uninterrupted = VmState::<u8>::new(8);
+    let mut expected = Vec::new();
+    uninterrupted.run(&program, &mut &input[..], &mut expected, &options, None, |_, _| ()).unwrap();
+
+    // stop at every possible point, save, and carry on from the saved state
+    for stop in 1..uninterrupted.steps {
+        let mut state = VmState::<u8>::new(8);
+        let mut output = Vec::new();
+        let limited = Options { max_steps: Some(stop), ..options.clone() };
+        let result = state.run(&program, &mut &input[..], &mut output, &limited, None, |_, _| ());
+        assert_eq!(result, Err(RuntimeError::StepLimitReached(stop, state.instr_pointer)));
+
+        let mut state = decode(&encode(&state, &program), &program).unwrap();
+        state.run(&program, &mut &input[state.input_read as usize..], &mut output, &options, None, |_, _| ()).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(state, uninterrupted);
+    }
+}
+
+#[test]
+fn states_only_load_for_the_program_and_cells_they_were_saved_with() {
+    let program = compile("+[>+<-]");
+    let saved = encode(&VmState::<u8>::new(4), &program);
+    assert!(matches!(decode::<u8>(&saved, &compile("+[>+<-].")), Err(StateError::DifferentProgram)));
+    assert!(matches!(decode::<u32>(&saved, &program), Err(StateError::WrongCellSize(8))));
+    assert!(matches!(decode::<u8>(&saved[..saved.len() - 1], &program), Err(StateError::Truncated)));
+    assert!(matches!(decode::<u8>(b"BFC\x01", &program), Err(StateError::NotAState)));
+}
@@ -0,0 +1,62 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use brainfuckers::{optimize_opcodes, parse, tokenize, transpile, EofBehavior};
+
+#[test]
+fn c_output_keeps_loop_nesting() {
+    let mut opcodes = tokenize("++[>[-]<-],.");
+    optimize_opcodes(&mut opcodes);
+    let program = parse(opcodes).unwrap();
+
+    let mut c_source = Vec::new();
+    transpile::to_c(&program, 16, 8, EofBehavior::Zero, &mut c_source).unwrap();
+    assert_eq!(String::from_utf8(c_source).unwrap(), "\
+#include <stdint.h>
+#include <stdio.h>
+
+#define TAPE_SIZE 16
+
+static uint8_t tape[TAPE_SIZE];
+
+int main(void) {
+    size_t p = 0;
+
+    tape[p] = 2u;
+    while (tape[p]) {
+        p = (p + 1) % TAPE_SIZE;
+        tape[p] = 0;
+        tape[(p + TAPE_SIZE - 1) % TAPE_SIZE] -= 1u;
+        p = (p + TAPE_SIZE - 1) % TAPE_SIZE;
+    }
+    fflush(stdout);
+    { int c = getchar(); tape[p] = c == EOF ? 0 : c; }
+    putchar(tape[p]);
+    return 0;
+}
+");
+}
+
+#[test]
+fn rust_output_compiles_and_runs() {
+    let mut opcodes = tokenize("++++++++[>++++[>++>+++<<-]>+<<-]>>.>+.[<]>>>,[.,]");
+    optimize_opcodes(&mut opcodes);
+    let program = parse(opcodes).unwrap();
+
+    let mut rust_source = Vec::new();
+    transpile::to_rust(&program, 8, EofBehavior::Zero, &mut rust_source).unwrap();
+    rust_source.extend_from_slice(b"fn main() { let mut tape = [0u8; 64]; run(&mut tape, &mut 0); }\n");
+
+    let dir = std::env::temp_dir();
+    let source_path = dir.join(format!("brainfuckers-{}-transpiled.rs", std::process::id()));
+    let binary_path = dir.join(format!("brainfuckers-{}-transpiled", std::process::id()));
+    std::fs::write(&source_path, rust_source).unwrap();
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let compiled = Command::new(rustc).arg("-o").arg(&binary_path).arg(&source_path).output().unwrap();
+    assert!(compiled.status.success(), "{}", String::from_utf8_lossy(&compiled.stderr));
+
+    let mut child = Command::new(&binary_path).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(b"echo").unwrap();
+    assert_eq!(child.wait_with_output().unwrap().stdout, b"Haecho");
+}
@@ -0,0 +1,28 @@
+#![cfg(feature = "wasm")]
+
+use brainfuckers::wasm::{bf_alloc, bf_free, bf_run, run};
+
+#[test]
+fn run_returns_the_output_or_the_error() {
+    assert_eq!(run(",+.", "a"), "b");
+    assert_eq!(run("+[", ""), "error: stray opening bracket at line 1, column 2");
+}
+
+#[test]
+fn output_comes_back_through_memory() {
+    let pass = |text: &str| {
+        let start = bf_alloc(text.len());
+        unsafe { std::ptr::copy_nonoverlapping(text.as_ptr(), start, text.len()) };
+        (start, text.len())
+    };
+    let (source, source_len) = pass("++++++++[>++++++++<-]>+.,.,.");
+    let (input, input_len) = pass("é");
+    unsafe {
+        let start = bf_run(source, source_len, input, input_len);
+        let len = u32::from_le_bytes(std::slice::from_raw_parts(start, 4).try_into().unwrap()) as usize;
+        assert_eq!(std::slice::from_raw_parts(start.add(4), len), "Aé".as_bytes());
+        bf_free(start, len + 4);
+        bf_free(source, source_len);
+        bf_free(input, input_len);
+    }
+}